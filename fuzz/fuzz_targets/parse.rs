@@ -0,0 +1,57 @@
+//! Throws arbitrary bytes at every parser, which have to return an error
+//! rather than panic however malformed the input is.
+//!
+//! Run with `cargo fuzz run parse` from the repository root.
+
+#![no_main]
+
+use gctconv::{Brres, FileInfo, Gct, Plt0, Tex0};
+use libfuzzer_sys::fuzz_target;
+
+/// Parsed textures larger than this aren't decoded, to keep runs quick.
+const MAX_DECODED_PIXELS: usize = 0x10000;
+
+/// Replaces the start of `data` with `magic`, so every input gets past the
+/// magic checks of each parser.
+fn with_magic(magic: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend(data.get(4..).unwrap_or(&[]));
+    bytes
+}
+
+fn small(width: u16, height: u16) -> bool {
+    width as usize * height as usize <= MAX_DECODED_PIXELS
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = FileInfo::read(data);
+
+    let gct_bytes = with_magic(b"GCNT", data);
+    let _ = FileInfo::read(&gct_bytes);
+    if let Ok(gct) = Gct::parse(&gct_bytes) {
+        if small(gct.width, gct.height) {
+            let _ = gct.to_rgba();
+        }
+        let _ = Tex0::from(&gct).to_bytes();
+    }
+
+    let tex0_bytes = with_magic(b"TEX0", data);
+    let _ = FileInfo::read(&tex0_bytes);
+    if let Ok(tex0) = Tex0::parse(&tex0_bytes) {
+        if small(tex0.width, tex0.height) {
+            let _ = tex0.to_rgba(None);
+        }
+        let _ = Gct::from(&tex0).to_bytes();
+    }
+
+    let plt0_bytes = with_magic(b"PLT0", data);
+    let _ = FileInfo::read(&plt0_bytes);
+    if let Ok(plt0) = Plt0::parse(&plt0_bytes) {
+        let _ = plt0.to_bytes();
+    }
+
+    let brres_bytes = with_magic(b"bres", data);
+    if let Ok(brres) = Brres::parse(&brres_bytes) {
+        let _ = brres.to_bytes();
+    }
+});
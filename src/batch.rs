@@ -0,0 +1,377 @@
+//! Expanding the command line inputs into the files to convert.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use gctconv::{GctconvError, TEXTURES};
+
+use crate::{
+    cli::{Args, Command},
+    file_stem, has_extension, output_path,
+};
+
+/// A single file to convert.
+pub struct Job {
+    pub input: PathBuf,
+    /// The PLT0 given or found for a TEX0 input.
+    pub palette: Option<PathBuf>,
+    /// The folder the input is in, relative to the folder or glob it was
+    /// found through. Outputs keep the same structure.
+    pub folder: PathBuf,
+}
+
+/// Expands every file, folder and glob in the inputs into jobs, sorted by
+/// input path.
+///
+/// A PLT0 named straight after a TEX0 goes with it; otherwise TEX0s are
+/// paired with the PLT0 of the same name that BrawlBox would export next to
+/// them. Two inputs that would be written to the same output, such as
+/// `a/x.gct` and `b/x.gct` named directly, are an error.
+pub fn collect(args: &Args) -> Result<Vec<Job>, GctconvError> {
+    let mut jobs: Vec<Job> = Vec::new();
+    // whether the last job is a TEX0 named directly just before this input,
+    // rather than whatever a folder or glob happened to list last
+    let mut after_tex0 = false;
+    for input in &args.inputs {
+        let named_tex0 = after_tex0;
+        after_tex0 = false;
+        if named_tex0 && args.command != Command::Info && has_extension(input, "plt0") {
+            if let Some(job) = jobs.last_mut() {
+                if job.palette.is_none() {
+                    job.palette = Some(input.clone());
+                    continue;
+                }
+            }
+        }
+
+        let (base, files) = if is_glob(input) {
+            expand_glob(input)?
+        } else if input.is_dir() {
+            let mut files = Vec::new();
+            walk(input, &mut files)?;
+            (input.clone(), files)
+        } else {
+            // files named directly are converted whatever their extension
+            after_tex0 = has_extension(input, "tex0");
+            jobs.push(Job {
+                input: input.clone(),
+                palette: None,
+                folder: PathBuf::new(),
+            });
+            continue;
+        };
+
+        for file in files {
+            let picked = args
+                .command
+                .input_extensions()
+                .iter()
+                .any(|ext| has_extension(&file, ext));
+            if !picked {
+                continue;
+            }
+
+            let folder = match file.parent().map(|p| p.strip_prefix(&base)) {
+                Some(Ok(folder)) => folder.to_owned(),
+                _ => PathBuf::new(),
+            };
+            jobs.push(Job {
+                input: file,
+                palette: None,
+                folder,
+            });
+        }
+    }
+
+    for job in &mut jobs {
+        if job.palette.is_none() && has_extension(&job.input, "tex0") {
+            job.palette = find_palette(&job.input);
+        }
+    }
+
+    jobs.sort_by(|a, b| a.input.cmp(&b.input));
+    jobs.dedup_by(|a, b| a.input == b.input);
+
+    // everything goes to the one path with -o, which main turns down
+    // for more than one job anyway
+    if args.output.is_none() {
+        let mut outputs: HashMap<PathBuf, &Path> = HashMap::new();
+        for job in &jobs {
+            if let Some(output) = output(args, job) {
+                if let Some(first) = outputs.get(&output) {
+                    return Err(GctconvError::SameOutput {
+                        inputs: (first.to_path_buf(), job.input.clone()),
+                        output,
+                    });
+                }
+                outputs.insert(output, &job.input);
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+/// The file or folder a job writes, going by its extension for `convert`,
+/// or `None` for the commands that don't write one per input.
+fn output(args: &Args, job: &Job) -> Option<PathBuf> {
+    let extension = match args.command {
+        Command::ToTex0 => "tex0",
+        Command::ToGct => "gct",
+        Command::ToPng => "png",
+        Command::Convert if has_extension(&job.input, "gct") => "tex0",
+        Command::Convert => "gct",
+        Command::ExtractGct => {
+            return Some(args.out_dir.join(&job.folder).join(file_stem(&job.input)))
+        }
+        Command::ToBrres | Command::Replace | Command::Check | Command::Info => return None,
+    };
+    let folder = if extension == "tex0" {
+        Some(TEXTURES)
+    } else {
+        None
+    };
+    Some(output_path(args, job, folder, extension))
+}
+
+/// Runs `convert` on every job across up to `threads` threads, handing
+/// each result to `report` in job order, as soon as it and every result
+/// before it are in.
+pub fn run_parallel<'a, T, C, R>(jobs: &'a [Job], threads: usize, convert: C, mut report: R)
+where
+    T: Send,
+    C: Fn(&Job) -> T + Sync,
+    R: FnMut(&'a Job, T),
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(jobs.len()) {
+            let sender = sender.clone();
+            let (next, convert) = (&next, &convert);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let job = match jobs.get(index) {
+                    Some(job) => job,
+                    None => break,
+                };
+                if sender.send((index, convert(job))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // results that came in before one of the jobs ahead of them
+        let mut waiting = BTreeMap::new();
+        let mut reported = 0;
+        for (index, result) in receiver {
+            waiting.insert(index, result);
+            while let Some(result) = waiting.remove(&reported) {
+                report(&jobs[reported], result);
+                reported += 1;
+            }
+        }
+    });
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// All the files matching a glob, and the folder before its first wildcard
+/// that their structure is kept relative to.
+fn expand_glob(pattern: &Path) -> Result<(PathBuf, Vec<PathBuf>), GctconvError> {
+    let base: PathBuf = pattern
+        .components()
+        .take_while(|c| !matches!(c, Component::Normal(name) if is_glob(Path::new(name))))
+        .collect();
+
+    let paths = glob::glob(&pattern.to_string_lossy())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(io::Error::from)?;
+        if path.is_dir() {
+            walk(&path, &mut files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok((base, files))
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Looks for `foo.plt0` in a `Palettes(NW4R)` folder beside the
+/// `Textures(NW4R)` folder `foo.tex0` is in, in a `Palettes(NW4R)` folder
+/// next to it, or right next to it.
+fn find_palette(tex0: &Path) -> Option<PathBuf> {
+    let folder = tex0.parent().unwrap_or_else(|| Path::new(""));
+    let name = tex0.with_extension("plt0");
+    let name = name.file_name()?;
+
+    let mut candidates = Vec::new();
+    if folder.file_name().is_some_and(|f| f == "Textures(NW4R)") {
+        if let Some(up) = folder.parent() {
+            candidates.push(up.join("Palettes(NW4R)").join(name));
+        }
+    }
+    candidates.push(folder.join("Palettes(NW4R)").join(name));
+    candidates.push(folder.join(name));
+
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder under the system's temporary folder, with an empty
+    /// file at each of `files`.
+    fn folder(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("gctconv-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"").unwrap();
+        }
+        root
+    }
+
+    fn args(command: &str, inputs: &[&Path]) -> Args {
+        let words = std::iter::once(command.to_owned())
+            .chain(inputs.iter().map(|p| p.to_string_lossy().into_owned()));
+        Args::parse(words).unwrap()
+    }
+
+    #[test]
+    fn inputs_writing_the_same_output() {
+        let root = folder("same-output", &["a/x.gct", "b/x.gct", "b/y.gct"]);
+        let (a, b) = (root.join("a/x.gct"), root.join("b/x.gct"));
+
+        let result = collect(&args("to-tex0", &[&a, &b]));
+        match result {
+            Err(GctconvError::SameOutput { inputs, .. }) => assert_eq!(inputs, (a, b)),
+            _ => panic!("both inputs were converted"),
+        }
+        // two folders given whole keep nothing of their own paths
+        let result = collect(&args("to-tex0", &[&root.join("a"), &root.join("b")]));
+        assert!(matches!(result, Err(GctconvError::SameOutput { .. })));
+        // but that of the folder they are found through
+        assert_eq!(collect(&args("to-tex0", &[&root])).unwrap().len(), 3);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn results_come_out_in_input_order() {
+        let jobs: Vec<Job> = (0..6)
+            .map(|i| Job {
+                input: PathBuf::from(i.to_string()),
+                palette: None,
+                folder: PathBuf::new(),
+            })
+            .collect();
+
+        // every job waits for the ones after it, so they finish backwards
+        let finished = AtomicUsize::new(0);
+        let mut reported = Vec::new();
+        run_parallel(
+            &jobs,
+            jobs.len(),
+            |job| {
+                let index: usize = job.input.to_str().unwrap().parse().unwrap();
+                while finished.load(Ordering::SeqCst) < jobs.len() - 1 - index {
+                    thread::yield_now();
+                }
+                finished.fetch_add(1, Ordering::SeqCst) + 1
+            },
+            |job, finished| reported.push((job.input.clone(), finished)),
+        );
+
+        let expected: Vec<(PathBuf, usize)> = (0..6)
+            .map(|i| (PathBuf::from(i.to_string()), 6 - i))
+            .collect();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn texture_folders_pair_with_palette_folders() {
+        let root = folder(
+            "pairs",
+            &[
+                "model/Textures(NW4R)/foo.tex0",
+                "model/Textures(NW4R)/bar.tex0",
+                "model/Palettes(NW4R)/foo.plt0",
+                "other/baz.tex0",
+                "other/baz.plt0",
+            ],
+        );
+        let jobs = collect(&args("to-gct", &[&root])).unwrap();
+
+        let found: Vec<(PathBuf, Option<PathBuf>, PathBuf)> = jobs
+            .into_iter()
+            .map(|job| {
+                let relative = |path: PathBuf| path.strip_prefix(&root).unwrap().to_owned();
+                (relative(job.input), job.palette.map(relative), job.folder)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    PathBuf::from("model/Textures(NW4R)/bar.tex0"),
+                    None,
+                    PathBuf::from("model/Textures(NW4R)"),
+                ),
+                (
+                    PathBuf::from("model/Textures(NW4R)/foo.tex0"),
+                    Some(PathBuf::from("model/Palettes(NW4R)/foo.plt0")),
+                    PathBuf::from("model/Textures(NW4R)"),
+                ),
+                (
+                    PathBuf::from("other/baz.tex0"),
+                    Some(PathBuf::from("other/baz.plt0")),
+                    PathBuf::from("other"),
+                ),
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn named_palettes_go_with_the_texture_before_them() {
+        let root = folder("named", &["a.tex0", "b.plt0", "c.tex0"]);
+        let (a, b, c) = (
+            root.join("a.tex0"),
+            root.join("b.plt0"),
+            root.join("c.tex0"),
+        );
+
+        let jobs = collect(&args("to-gct", &[&a, &b, &c])).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].palette, Some(b));
+        assert_eq!(jobs[1].palette, None);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,718 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    check_magic, check_magic_at, read_bytes, read_string, read_u16, read_u32, tex0, GctconvError,
+    Plt0, Tex0,
+};
+
+/// The folder BrawlBox and the games keep textures in.
+pub const TEXTURES: &str = "Textures(NW4R)";
+/// The folder BrawlBox and the games keep palettes in.
+pub const PALETTES: &str = "Palettes(NW4R)";
+
+/// The root section always directly follows the 0x10 byte header.
+const ROOT_OFFSET: usize = 0x10;
+
+/// An NW4R resource archive (`bres`), the file Wii games load models,
+/// textures and animations from.
+///
+/// The root section holds an index group of folders, each an index group of
+/// sub-files, and every name is kept once in a string table at the end.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Brres {
+    pub folders: Vec<Folder>,
+}
+
+/// A named group of sub-files, such as `Textures(NW4R)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Folder {
+    pub name: String,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The name in the index group, which is the one the game looks up; the
+    /// name inside a `Tex0` or `Plt0` is ignored when writing.
+    pub name: String,
+    /// Where the sub-file starts in the archive it was read from, 0 for one
+    /// that wasn't read.
+    pub offset: usize,
+    pub file: SubFile,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubFile {
+    Tex0(Tex0),
+    Plt0(Plt0),
+    /// Any other sub-file, such as a MDL0, from its magic to the end of its
+    /// declared size.
+    Other(Vec<u8>),
+}
+
+impl Brres {
+    pub fn parse(bytes: &[u8]) -> Result<Brres, GctconvError> {
+        check_magic(bytes, b"bres")?;
+
+        let bom = read_u16(bytes, 0x04)?;
+        if bom != 0xFEFF {
+            return Err(GctconvError::BadHeaderValue {
+                offset: 0x04,
+                value: bom as usize,
+            });
+        }
+        let root = read_u16(bytes, 0x0C)? as usize;
+        check_magic_at(bytes, root, b"root")?;
+
+        let mut folders = Vec::new();
+        for group in read_group(bytes, root + 0x08)? {
+            let mut entries = Vec::new();
+            for entry in read_group(bytes, group.data_offset)? {
+                entries.push(Entry {
+                    file: SubFile::parse(bytes, entry.data_offset)?,
+                    name: entry.name,
+                    offset: entry.data_offset,
+                });
+            }
+            folders.push(Folder {
+                name: group.name,
+                entries,
+            });
+        }
+        Ok(Brres { folders })
+    }
+
+    /// Builds an archive of textures and the palettes that go with them, in
+    /// the folders BrawlBox puts them in, each named as it is named itself.
+    pub fn from_textures(textures: Vec<Tex0>, palettes: Vec<Plt0>) -> Brres {
+        let mut folders = vec![Folder {
+            name: TEXTURES.to_owned(),
+            entries: textures
+                .into_iter()
+                .map(|tex0| Entry {
+                    name: tex0.name.clone(),
+                    offset: 0,
+                    file: SubFile::Tex0(tex0),
+                })
+                .collect(),
+        }];
+        if !palettes.is_empty() {
+            folders.push(Folder {
+                name: PALETTES.to_owned(),
+                entries: palettes
+                    .into_iter()
+                    .map(|plt0| Entry {
+                        name: plt0.name.clone(),
+                        offset: 0,
+                        file: SubFile::Plt0(plt0),
+                    })
+                    .collect(),
+            });
+        }
+        Brres { folders }
+    }
+
+    pub fn folder(&self, name: &str) -> Option<&Folder> {
+        self.folders.iter().find(|f| f.name == name)
+    }
+
+    /// Swaps the texture `name` in the archive `bytes` for `tex0`, and its
+    /// palette for `plt0`, leaving every other byte of the archive as it
+    /// is.
+    ///
+    /// A sub-file that fits goes where the old one was; one that doesn't is
+    /// added to the end of the archive instead, since moving anything else
+    /// would break the offsets MDL0s and animations keep into the string
+    /// table. The old palette is kept if `plt0` is `None`, but there has to
+    /// be one to swap `plt0` for.
+    ///
+    /// Adding a palette would mean growing the index groups, and removing
+    /// one would leave a PLT0 nothing uses, so a texture can't be swapped
+    /// for one that has a palette where it had none, or the other way round.
+    pub fn replace_texture(
+        bytes: &[u8],
+        name: &str,
+        tex0: &Tex0,
+        plt0: Option<&Plt0>,
+    ) -> Result<Vec<u8>, GctconvError> {
+        let missing = |folder| GctconvError::MissingEntry {
+            folder,
+            name: name.to_owned(),
+        };
+        let texture = find_entry(bytes, TEXTURES, name)?.ok_or_else(|| missing(TEXTURES))?;
+        let old = Tex0::parse_at(bytes, texture.data_offset)?;
+        if old.encoding.has_palette() != tex0.encoding.has_palette() {
+            return Err(GctconvError::PaletteChange {
+                name: name.to_owned(),
+                from: old.encoding,
+                to: tex0.encoding,
+            });
+        }
+        let palette = match plt0 {
+            Some(plt0) => match find_entry(bytes, PALETTES, name)? {
+                Some(entry) => Some((entry, plt0)),
+                None => return Err(missing(PALETTES)),
+            },
+            None => None,
+        };
+
+        let mut brres = bytes.to_vec();
+        let name_field = tex0::name_offset_field(tex0.version);
+        put_sub_file(&mut brres, &texture, tex0.header_and_data()?, name_field)?;
+        if let Some((entry, plt0)) = palette {
+            put_sub_file(&mut brres, &entry, plt0.header_and_data(), 0x14)?;
+        }
+
+        let size = brres.len() as u32;
+        brres[0x08..0x0C].copy_from_slice(&size.to_be_bytes());
+        Ok(brres)
+    }
+
+    /// Lays the archive out the way BrawlBox does: the root section, then
+    /// every sub-file 32 byte aligned, then the string table.
+    ///
+    /// Only TEX0s and PLT0s can be written; other sub-files point into the
+    /// string table from places gctconv doesn't know about.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GctconvError> {
+        // every folder and sub-file name is in the string table once, with
+        // the offset of its first character
+        let mut strings = BTreeMap::new();
+        for folder in &self.folders {
+            strings.insert(folder.name.as_str(), 0);
+            for entry in &folder.entries {
+                strings.insert(entry.name.as_str(), 0);
+            }
+        }
+
+        // the root group, then a group for each folder
+        let root_group = ROOT_OFFSET + 0x08;
+        let mut end = root_group + group_size(self.folders.len());
+        let mut folder_groups = Vec::new();
+        for folder in &self.folders {
+            folder_groups.push(end);
+            end += group_size(folder.entries.len());
+        }
+        end = align(end, 0x20);
+        let root_size = end - ROOT_OFFSET;
+
+        let mut files = Vec::new();
+        for folder in &self.folders {
+            for entry in &folder.entries {
+                let (file, name_field) = entry.file.header_and_data()?;
+                let next = align(end + file.len(), 0x20);
+                files.push((end, file, name_field, entry.name.as_str()));
+                end = next;
+            }
+        }
+
+        let mut string_table = Vec::new();
+        for (name, offset) in strings.iter_mut() {
+            if !name.is_ascii() {
+                return Err(GctconvError::NonAsciiName((*name).to_owned()));
+            }
+            if name.len() > u8::MAX as usize {
+                return Err(GctconvError::NameTooLong((*name).to_owned()));
+            }
+            string_table.extend(&(name.len() as u32).to_be_bytes());
+            *offset = end + string_table.len();
+            string_table.extend(name.as_bytes());
+            // null terminated, and padded to the next 4th byte
+            string_table.push(0);
+            string_table.resize(align(string_table.len(), 4), 0);
+        }
+        let size = end + string_table.len();
+
+        let mut brres = b"bres".to_vec();
+        brres.extend(&0xFEFF_u16.to_be_bytes());
+        brres.extend(&[0; 2]);
+        brres.extend(&(size as u32).to_be_bytes());
+        brres.extend(&(ROOT_OFFSET as u16).to_be_bytes());
+        // the root section and every sub-file
+        brres.extend(&((1 + files.len()) as u16).to_be_bytes());
+
+        brres.extend(b"root");
+        brres.extend(&(root_size as u32).to_be_bytes());
+        let folders: Vec<_> = self
+            .folders
+            .iter()
+            .zip(&folder_groups)
+            .map(|(folder, &group)| (folder.name.as_str(), group))
+            .collect();
+        write_group(&mut brres, &folders, &strings)?;
+
+        let mut next_file = files.iter();
+        for folder in &self.folders {
+            let entries: Vec<_> = folder
+                .entries
+                .iter()
+                .zip(next_file.by_ref())
+                .map(|(entry, file)| (entry.name.as_str(), file.0))
+                .collect();
+            write_group(&mut brres, &entries, &strings)?;
+        }
+
+        for (offset, mut file, name_field, name) in files {
+            brres.resize(offset, 0);
+            // sub-files point back at the start of the archive, and on to
+            // their name in the string table
+            let brres_offset = -(offset as i32);
+            file[0x0C..0x10].copy_from_slice(&brres_offset.to_be_bytes());
+            let name_offset = (strings[name] - offset) as u32;
+            file[name_field..name_field + 4].copy_from_slice(&name_offset.to_be_bytes());
+            brres.extend(file);
+        }
+        brres.resize(end, 0);
+        brres.extend(string_table);
+        Ok(brres)
+    }
+}
+
+impl SubFile {
+    fn parse(bytes: &[u8], offset: usize) -> Result<SubFile, GctconvError> {
+        match read_bytes(bytes, offset, 4)? {
+            b"TEX0" => Ok(SubFile::Tex0(Tex0::parse_at(bytes, offset)?)),
+            b"PLT0" => Ok(SubFile::Plt0(Plt0::parse_at(bytes, offset)?)),
+            _ => {
+                let size = read_u32(bytes, offset.saturating_add(0x04))? as usize;
+                Ok(SubFile::Other(read_bytes(bytes, offset, size)?.to_vec()))
+            }
+        }
+    }
+
+    /// The sub-file without a name, and where its name offset goes.
+    fn header_and_data(&self) -> Result<(Vec<u8>, usize), GctconvError> {
+        match self {
+            SubFile::Tex0(tex0) => Ok((
+                tex0.header_and_data()?,
+                tex0::name_offset_field(tex0.version),
+            )),
+            SubFile::Plt0(plt0) => Ok((plt0.header_and_data(), 0x14)),
+            SubFile::Other(bytes) => {
+                let mut magic = [0; 4];
+                magic.copy_from_slice(bytes.get(..4).unwrap_or(b"????"));
+                Err(GctconvError::UnsupportedSubFile(magic))
+            }
+        }
+    }
+}
+
+/// Size of an index group of `count` entries, counting the root entry.
+fn group_size(count: usize) -> usize {
+    0x08 + 0x10 * (count + 1)
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// An entry of an index group as it is in the file.
+struct GroupEntry {
+    name: String,
+    /// Where the group the entry is in starts.
+    group: usize,
+    /// Where the entry itself is.
+    entry: usize,
+    /// Where the first character of the name is.
+    name_offset: usize,
+    /// Where what the entry names starts.
+    data_offset: usize,
+}
+
+/// The entries of the index group at `group`. Both offsets in each entry are
+/// from the start of the group.
+fn read_group(bytes: &[u8], group: usize) -> Result<Vec<GroupEntry>, GctconvError> {
+    let count = read_u32(bytes, group.saturating_add(0x04))? as usize;
+
+    let mut entries = Vec::new();
+    // entry 0 is the root of the search tree, which names nothing
+    for i in 1..=count {
+        let entry = group.saturating_add(0x08 + 0x10 * i);
+        let name_offset = read_u32(bytes, entry.saturating_add(0x08))? as usize;
+        let data_offset = read_u32(bytes, entry.saturating_add(0x0C))? as usize;
+
+        let name_offset = group.saturating_add(name_offset);
+        let name = match name_offset.checked_sub(4) {
+            Some(len_offset) => read_string(bytes, len_offset)?,
+            None => {
+                return Err(GctconvError::BadHeaderValue {
+                    offset: entry + 0x08,
+                    value: name_offset - group,
+                })
+            }
+        };
+        entries.push(GroupEntry {
+            name,
+            group,
+            entry,
+            name_offset,
+            data_offset: group.saturating_add(data_offset),
+        });
+    }
+    Ok(entries)
+}
+
+/// The entry named `name` in `folder` of the archive, if there is one.
+fn find_entry(bytes: &[u8], folder: &str, name: &str) -> Result<Option<GroupEntry>, GctconvError> {
+    check_magic(bytes, b"bres")?;
+    let root = read_u16(bytes, 0x0C)? as usize;
+    check_magic_at(bytes, root, b"root")?;
+
+    for group in read_group(bytes, root + 0x08)? {
+        if group.name == folder {
+            let entries = read_group(bytes, group.data_offset)?;
+            return Ok(entries.into_iter().find(|e| e.name == name));
+        }
+    }
+    Ok(None)
+}
+
+/// Puts the nameless sub-file `file` where `entry` points, or at the end of
+/// the archive if it is bigger than what is there now, pointing it at the
+/// name `entry` uses. The old sub-file is cleared either way.
+fn put_sub_file(
+    brres: &mut Vec<u8>,
+    entry: &GroupEntry,
+    mut file: Vec<u8>,
+    name_field: usize,
+) -> Result<(), GctconvError> {
+    let old_offset = entry.data_offset;
+    let old_size = read_u32(brres, old_offset.saturating_add(0x04))? as usize;
+    read_bytes(brres, old_offset, old_size)?;
+    brres[old_offset..old_offset + old_size].fill(0);
+
+    let offset = if file.len() <= old_size {
+        old_offset
+    } else {
+        let offset = align(brres.len(), 0x20);
+        brres.resize(offset + file.len(), 0);
+        // the group entry's data offset is from the start of its group
+        let data_offset = (offset - entry.group) as u32;
+        brres[entry.entry + 0x0C..entry.entry + 0x10].copy_from_slice(&data_offset.to_be_bytes());
+        offset
+    };
+
+    let brres_offset = -(offset as i32);
+    file[0x0C..0x10].copy_from_slice(&brres_offset.to_be_bytes());
+    // the string table can be before the sub-file now
+    let name_offset = (entry.name_offset as i64 - offset as i64) as i32;
+    file[name_field..name_field + 4].copy_from_slice(&name_offset.to_be_bytes());
+    brres[offset..offset + file.len()].copy_from_slice(&file);
+    Ok(())
+}
+
+/// Writes an index group of `entries`, each a name and where what it names
+/// starts, at the end of `brres`.
+fn write_group(
+    brres: &mut Vec<u8>,
+    entries: &[(&str, usize)],
+    strings: &BTreeMap<&str, usize>,
+) -> Result<(), GctconvError> {
+    let group = brres.len();
+    let names: Vec<&str> = entries.iter().map(|e| e.0).collect();
+    let tree = build_tree(&names)?;
+
+    brres.extend(&(group_size(entries.len()) as u32).to_be_bytes());
+    brres.extend(&(entries.len() as u32).to_be_bytes());
+    for (i, node) in tree.iter().enumerate() {
+        brres.extend(&node.id.to_be_bytes());
+        brres.extend(&[0; 2]);
+        brres.extend(&node.left.to_be_bytes());
+        brres.extend(&node.right.to_be_bytes());
+        let (name_offset, data_offset) = match i.checked_sub(1) {
+            Some(i) => (strings[entries[i].0] - group, entries[i].1 - group),
+            None => (0, 0),
+        };
+        brres.extend(&(name_offset as u32).to_be_bytes());
+        brres.extend(&(data_offset as u32).to_be_bytes());
+    }
+    Ok(())
+}
+
+/// An entry of an index group's search tree. The game walks down from the
+/// root, going right where the name it looks for has bit `id` set, until it
+/// comes back up to an entry with a higher `id`.
+struct Node {
+    id: u16,
+    left: u16,
+    right: u16,
+}
+
+/// Bit `id` of `name`, counting bytes from the end of the name, so that
+/// names of different lengths always differ somewhere.
+fn bit(name: &[u8], id: u16) -> bool {
+    let index = id as usize >> 3;
+    index < name.len() && (name[name.len() - 1 - index] >> (id & 7)) & 1 == 1
+}
+
+/// Builds the Patricia tree of an index group, with the root entry first
+/// followed by an entry for each of `names`.
+fn build_tree(names: &[&str]) -> Result<Vec<Node>, GctconvError> {
+    let mut nodes = vec![Node {
+        id: 0xFFFF,
+        left: 0,
+        right: 0,
+    }];
+    let child = |node: &Node, name: &[u8]| {
+        if bit(name, node.id) {
+            node.right as usize
+        } else {
+            node.left as usize
+        }
+    };
+
+    for (i, name) in names.iter().enumerate() {
+        let index = i + 1;
+        let name = name.as_bytes();
+
+        // the entry a search for the name ends up at now
+        let (mut parent, mut current) = (0, nodes[0].left as usize);
+        while nodes[parent].id > nodes[current].id {
+            parent = current;
+            current = child(&nodes[current], name);
+        }
+        let found = match current {
+            0 => &b""[..],
+            n => names[n - 1].as_bytes(),
+        };
+
+        // the first bit searches test that tells the two apart
+        let longest = name.len().max(found.len());
+        let id = match (0..longest * 8)
+            .rev()
+            .map(|id| id as u16)
+            .find(|&id| bit(name, id) != bit(found, id))
+        {
+            Some(id) => id,
+            None => return Err(GctconvError::DuplicateName(names[i].to_owned())),
+        };
+
+        // the new entry goes in above the first entry testing a later bit
+        let (mut parent, mut current) = (0, nodes[0].left as usize);
+        while nodes[parent].id > nodes[current].id && nodes[current].id > id {
+            parent = current;
+            current = child(&nodes[current], name);
+        }
+        let (left, right) = if bit(name, id) {
+            (current, index)
+        } else {
+            (index, current)
+        };
+        nodes.push(Node {
+            id,
+            left: left as u16,
+            right: right as u16,
+        });
+        if parent == 0 || !bit(name, nodes[parent].id) {
+            nodes[parent].left = index as u16;
+        } else {
+            nodes[parent].right = index as u16;
+        }
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EncodingType, PaletteFormat};
+
+    fn texture(name: &str, size: u16, encoding: EncodingType) -> Tex0 {
+        Tex0 {
+            name: name.to_owned(),
+            width: size,
+            height: size,
+            encoding,
+            data: (0..encoding.image_size(size as usize, size as usize))
+                .map(|i| i as u8)
+                .collect(),
+            levels: 1,
+            version: 1,
+        }
+    }
+
+    fn palette(name: &str, entries: usize) -> Plt0 {
+        Plt0 {
+            name: name.to_owned(),
+            format: PaletteFormat::Rgb565,
+            data: (0..entries * 2).map(|i| (i * 3) as u8).collect(),
+        }
+    }
+
+    fn archive(names: &[&str]) -> Brres {
+        let textures = names
+            .iter()
+            .map(|name| texture(name, 8, EncodingType::I8))
+            .collect();
+        Brres::from_textures(textures, Vec::new())
+    }
+
+    /// Searches the index group at `group` for `name` the way the game
+    /// does, returning the name of the entry the search ends at.
+    fn look_up(bytes: &[u8], group: usize, name: &str) -> String {
+        let node = |i: usize| {
+            let entry = group + 0x08 + 0x10 * i;
+            let id = read_u16(bytes, entry).unwrap();
+            let left = read_u16(bytes, entry + 0x04).unwrap() as usize;
+            let right = read_u16(bytes, entry + 0x06).unwrap() as usize;
+            (id, left, right)
+        };
+
+        let (mut parent, mut current) = (0, node(0).1);
+        while node(parent).0 > node(current).0 {
+            parent = current;
+            let (id, left, right) = node(current);
+            current = if bit(name.as_bytes(), id) {
+                right
+            } else {
+                left
+            };
+        }
+        let entry = group + 0x08 + 0x10 * current;
+        let name_offset = read_u32(bytes, entry + 0x08).unwrap() as usize;
+        read_string(bytes, group + name_offset - 4).unwrap()
+    }
+
+    /// Where the index group of `folder` starts.
+    fn folder_group(bytes: &[u8], folder: &str) -> usize {
+        read_group(bytes, ROOT_OFFSET + 0x08)
+            .unwrap()
+            .into_iter()
+            .find(|group| group.name == folder)
+            .unwrap()
+            .data_offset
+    }
+
+    #[test]
+    fn round_trip() {
+        let textures = vec![
+            texture("a", 8, EncodingType::I8),
+            texture("aa", 16, EncodingType::Ci8),
+            texture("ab", 8, EncodingType::Rgb5A3),
+        ];
+        let palettes = vec![palette("aa", 256)];
+        let brres = Brres::from_textures(textures.clone(), palettes.clone());
+
+        let parsed = Brres::parse(&brres.to_bytes().unwrap()).unwrap();
+        let names: Vec<_> = parsed.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, [TEXTURES, PALETTES]);
+
+        let files = |folder: &str| -> Vec<SubFile> {
+            let folder = parsed.folder(folder).unwrap();
+            folder.entries.iter().map(|e| e.file.clone()).collect()
+        };
+        let expected: Vec<_> = textures.into_iter().map(SubFile::Tex0).collect();
+        assert_eq!(files(TEXTURES), expected);
+        let expected: Vec<_> = palettes.into_iter().map(SubFile::Plt0).collect();
+        assert_eq!(files(PALETTES), expected);
+    }
+
+    #[test]
+    fn sub_files_are_aligned() {
+        let bytes = archive(&["a", "aa", "ab"]).to_bytes().unwrap();
+        let parsed = Brres::parse(&bytes).unwrap();
+        for entry in &parsed.folder(TEXTURES).unwrap().entries {
+            assert_eq!(entry.offset % 0x20, 0, "{}", entry.name);
+        }
+        assert_eq!(read_u32(&bytes, 0x08).unwrap() as usize, bytes.len());
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let result = archive(&["a", "ab", "a"]).to_bytes();
+        assert!(matches!(result, Err(GctconvError::DuplicateName(name)) if name == "a"));
+    }
+
+    #[test]
+    fn tree_finds_every_name() {
+        let sets: [&[&str]; 4] = [
+            &["a"],
+            &["a", "aa", "ab"],
+            &["ab", "aa", "a", "b", "ba"],
+            &["tex_00", "tex_01", "tex_10", "tex_0", "tex", "x"],
+        ];
+        for names in sets {
+            let bytes = archive(names).to_bytes().unwrap();
+            let group = folder_group(&bytes, TEXTURES);
+            for name in names {
+                assert_eq!(look_up(&bytes, group, name), *name, "in {:?}", names);
+            }
+
+            let root = ROOT_OFFSET + 0x08;
+            assert_eq!(look_up(&bytes, root, TEXTURES), TEXTURES);
+        }
+    }
+
+    #[test]
+    fn tree_misses_other_names() {
+        let bytes = archive(&["a", "aa", "ab"]).to_bytes().unwrap();
+        let group = folder_group(&bytes, TEXTURES);
+        for name in ["b", "ac", "aaa"] {
+            assert_ne!(look_up(&bytes, group, name), name);
+        }
+    }
+
+    #[test]
+    fn replace_in_place() {
+        let bytes = archive(&["a", "aa", "ab"]).to_bytes().unwrap();
+        let mut new = texture("aa", 8, EncodingType::I8);
+        new.data.reverse();
+
+        let replaced = Brres::replace_texture(&bytes, "aa", &new, None).unwrap();
+        assert_eq!(replaced.len(), bytes.len());
+
+        let before = Brres::parse(&bytes).unwrap();
+        let after = Brres::parse(&replaced).unwrap();
+        let (before, after) = (
+            &before.folder(TEXTURES).unwrap().entries,
+            &after.folder(TEXTURES).unwrap().entries,
+        );
+        assert_eq!(after[1].offset, before[1].offset);
+        assert_eq!(after[1].file, SubFile::Tex0(new));
+        assert_eq!(after[0], before[0]);
+        assert_eq!(after[2], before[2]);
+    }
+
+    #[test]
+    fn replace_appends_larger_textures() {
+        let bytes = archive(&["a", "aa", "ab"]).to_bytes().unwrap();
+        let new = texture("aa", 32, EncodingType::I8);
+
+        let replaced = Brres::replace_texture(&bytes, "aa", &new, None).unwrap();
+        assert_eq!(read_u32(&replaced, 0x08).unwrap() as usize, replaced.len());
+
+        let after = Brres::parse(&replaced).unwrap();
+        let entries = &after.folder(TEXTURES).unwrap().entries;
+        assert!(entries[1].offset >= bytes.len());
+        assert_eq!(entries[1].offset % 0x20, 0);
+        assert_eq!(entries[1].file, SubFile::Tex0(new));
+
+        // only the archive size and the entry's data offset change before
+        // the old texture
+        let old = Brres::parse(&bytes).unwrap();
+        let end = old.folder(TEXTURES).unwrap().entries[1].offset;
+        let entry = folder_group(&bytes, TEXTURES) + 0x08 + 0x10 * 2;
+        assert_eq!(replaced[..0x08], bytes[..0x08]);
+        assert_eq!(replaced[0x0C..entry + 0x0C], bytes[0x0C..entry + 0x0C]);
+        assert_eq!(replaced[entry + 0x10..end], bytes[entry + 0x10..end]);
+    }
+
+    #[test]
+    fn replace_keeps_palettes_paired() {
+        let textures = vec![texture("a", 16, EncodingType::Ci8)];
+        let bytes = Brres::from_textures(textures, vec![palette("a", 256)])
+            .to_bytes()
+            .unwrap();
+
+        let new = texture("a", 16, EncodingType::Ci4);
+        let replaced = Brres::replace_texture(&bytes, "a", &new, Some(&palette("a", 16))).unwrap();
+        let after = Brres::parse(&replaced).unwrap();
+        assert_eq!(
+            after.folder(PALETTES).unwrap().entries[0].file,
+            SubFile::Plt0(palette("a", 16))
+        );
+
+        let new = texture("a", 16, EncodingType::I8);
+        let result = Brres::replace_texture(&bytes, "a", &new, None);
+        assert!(matches!(result, Err(GctconvError::PaletteChange { .. })));
+    }
+}
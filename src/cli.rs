@@ -0,0 +1,330 @@
+//! Command line parsing for the `gctconv` binary.
+
+use std::{path::PathBuf, thread};
+
+use gctconv::{
+    CmprQuality, Dither, EncodeOptions, EncodingType, FileKind, Filter, Fit, GctconvError,
+    PaletteFormat, Tex0,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Converts each file to whatever it is usually converted to, going by
+    /// its magic.
+    Convert,
+    ToTex0,
+    ToGct,
+    ToPng,
+    /// Packs every texture into one BRRES.
+    ToBrres,
+    /// Writes a GCT for every texture in a BRRES.
+    ExtractGct,
+    /// Swaps one texture in a BRRES for a GCT or PNG.
+    Replace,
+    /// Reports whatever about each texture the encoding or hardware won't
+    /// accept.
+    Check,
+    Info,
+}
+
+impl Command {
+    /// Looks up a subcommand, also accepting the older `-tex0` style flags.
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "convert" => Some(Command::Convert),
+            "to-tex0" | "-tex0" => Some(Command::ToTex0),
+            "to-gct" | "-gct" => Some(Command::ToGct),
+            "to-png" | "-png" => Some(Command::ToPng),
+            "to-brres" => Some(Command::ToBrres),
+            "extract-gct" => Some(Command::ExtractGct),
+            "replace" => Some(Command::Replace),
+            "check" => Some(Command::Check),
+            "info" => Some(Command::Info),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Convert => "convert",
+            Command::ToTex0 => "to-tex0",
+            Command::ToGct => "to-gct",
+            Command::ToPng => "to-png",
+            Command::ToBrres => "to-brres",
+            Command::ExtractGct => "extract-gct",
+            Command::Replace => "replace",
+            Command::Check => "check",
+            Command::Info => "info",
+        }
+    }
+
+    /// The kinds of file the command reads.
+    pub fn inputs(self) -> &'static [FileKind] {
+        match self {
+            Command::Convert => &[FileKind::Gct, FileKind::Tex0, FileKind::Png],
+            Command::ToTex0 => &[FileKind::Gct, FileKind::Png],
+            Command::ToGct => &[FileKind::Tex0, FileKind::Png],
+            Command::ToPng => &[FileKind::Gct, FileKind::Tex0],
+            Command::ToBrres => &[FileKind::Gct, FileKind::Tex0, FileKind::Png],
+            Command::ExtractGct => &[FileKind::Brres],
+            // the new texture; the archive is always a BRRES
+            Command::Replace => &[FileKind::Gct, FileKind::Png],
+            Command::Check => &[FileKind::Gct, FileKind::Tex0, FileKind::Brres],
+            Command::Info => &[FileKind::Gct, FileKind::Tex0, FileKind::Plt0],
+        }
+    }
+
+    /// The extensions of the files the command picks up from folders and
+    /// globs.
+    pub fn input_extensions(self) -> &'static [&'static str] {
+        match self {
+            Command::Convert => &["gct", "tex0", "png"],
+            Command::ToTex0 => &["gct", "png"],
+            Command::ToGct => &["tex0", "png"],
+            Command::ToPng => &["gct", "tex0"],
+            Command::ToBrres => &["gct", "tex0", "png"],
+            Command::ExtractGct => &["brres"],
+            Command::Replace => &["gct", "png"],
+            Command::Check => &["gct", "tex0", "brres"],
+            Command::Info => &["gct", "tex0", "plt0"],
+        }
+    }
+
+    /// The command that converts a file of `kind`: `convert` turns GCTs into
+    /// TEX0s, and TEX0s and PNGs into GCTs.
+    pub fn for_kind(self, kind: FileKind) -> Result<Command, GctconvError> {
+        if !self.inputs().contains(&kind) {
+            return Err(GctconvError::WrongFormat {
+                found: kind,
+                expected: self.inputs(),
+            });
+        }
+
+        Ok(match (self, kind) {
+            (Command::Convert, FileKind::Gct) => Command::ToTex0,
+            (Command::Convert, _) => Command::ToGct,
+            _ => self,
+        })
+    }
+}
+
+/// How TEX0s and PLT0s are laid out in the output directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// In `Textures(NW4R)` and `Palettes(NW4R)` folders, named after the
+    /// BRRES groups they belong in, as BrawlBox exports them.
+    BrawlBox,
+    /// Straight into the output directory.
+    Flat,
+}
+
+impl Layout {
+    fn from_name(name: &str) -> Option<Layout> {
+        if name.eq_ignore_ascii_case("brawlbox") {
+            Some(Layout::BrawlBox)
+        } else if name.eq_ignore_ascii_case("flat") {
+            Some(Layout::Flat)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Args {
+    pub command: Command,
+    /// Files, folders and globs to convert.
+    pub inputs: Vec<PathBuf>,
+    /// The exact path to write to, given with `-o`.
+    pub output: Option<PathBuf>,
+    pub out_dir: PathBuf,
+    pub layout: Layout,
+    /// The version of the TEX0s written.
+    pub tex0_version: u32,
+    /// How many images the mip chains generated have, the texture included;
+    /// `u32::MAX` for as many as the size allows.
+    pub mip_levels: Option<u32>,
+    pub mip_filter: Filter,
+    /// The encoding PNGs are imported with.
+    pub format: Option<EncodingType>,
+    pub options: EncodeOptions,
+    /// How many files are converted at once.
+    pub jobs: usize,
+    /// Report on each file as a line of JSON.
+    pub json: bool,
+    /// Replace textures even with one of another size or encoding.
+    pub force: bool,
+}
+
+impl Args {
+    /// Parses the arguments after the program name, returning a message for
+    /// the user if they don't make sense.
+    pub fn parse<I>(args: I) -> Result<Args, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut command = None;
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut out_dir = PathBuf::from("output");
+        let mut layout = Layout::BrawlBox;
+        let mut tex0_version = 1;
+        let mut mip_levels = None;
+        let mut mip_filter = Filter::default();
+        let mut format = None;
+        let mut options = EncodeOptions::default();
+        let mut jobs = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut json = false;
+        let mut force = false;
+        // after "--" everything is an input, even a texture name that
+        // starts with "-"
+        let mut only_inputs = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if command.is_none() {
+                match Command::from_name(&arg) {
+                    Some(c) => command = Some(c),
+                    None => return Err(format!("Invalid operating mode \"{}\".", arg)),
+                }
+                continue;
+            }
+
+            if only_inputs || !arg.starts_with('-') {
+                inputs.push(PathBuf::from(arg));
+                continue;
+            }
+            if arg == "--" {
+                only_inputs = true;
+                continue;
+            }
+
+            if arg == "--json" {
+                json = true;
+                continue;
+            }
+            if arg == "--force" {
+                force = true;
+                continue;
+            }
+
+            let mut value = || match args.next() {
+                Some(v) => Ok(v),
+                None => Err(format!("{} needs a value.", arg)),
+            };
+            match arg.as_str() {
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                "--out-dir" => out_dir = PathBuf::from(value()?),
+                "--layout" => {
+                    let name = value()?;
+                    layout = Layout::from_name(&name)
+                        .ok_or_else(|| format!("Unknown layout \"{}\".", name))?;
+                }
+                "--tex0-version" => {
+                    tex0_version = match value()?.parse() {
+                        Ok(v) if Tex0::VERSIONS.contains(&v) => v,
+                        _ => return Err(format!("{} needs 1, 2 or 3.", arg)),
+                    };
+                }
+                "--mip-levels" => {
+                    mip_levels = match value()?.as_str() {
+                        "full" => Some(u32::MAX),
+                        v => match v.parse() {
+                            Ok(n) if n > 0 => Some(n),
+                            _ => return Err(format!("{} needs a number above 0 or full.", arg)),
+                        },
+                    };
+                }
+                "--mip-filter" => {
+                    let name = value()?;
+                    mip_filter = Filter::from_name(&name)
+                        .ok_or_else(|| format!("Unknown filter \"{}\".", name))?;
+                }
+                "-j" | "--jobs" => {
+                    jobs = match value()?.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("{} needs a number above 0.", arg)),
+                    };
+                }
+                "--format" => {
+                    let name = value()?;
+                    format = Some(
+                        EncodingType::from_name(&name)
+                            .ok_or_else(|| format!("Unknown encoding \"{}\".", name))?,
+                    );
+                }
+                "--quality" => {
+                    let name = value()?;
+                    options.cmpr_quality = CmprQuality::from_name(&name)
+                        .ok_or_else(|| format!("Unknown CMPR quality \"{}\".", name))?;
+                }
+                "--dither" => {
+                    let name = value()?;
+                    options.dither = Dither::from_name(&name)
+                        .ok_or_else(|| format!("Unknown dithering \"{}\".", name))?;
+                }
+                "--fit" => {
+                    let name = value()?;
+                    options.fit = Some(
+                        Fit::from_name(&name)
+                            .ok_or_else(|| format!("Unknown fit \"{}\".", name))?,
+                    );
+                }
+                "--fit-filter" => {
+                    let name = value()?;
+                    options.fit_filter = Filter::from_name(&name)
+                        .ok_or_else(|| format!("Unknown filter \"{}\".", name))?;
+                }
+                "--palette-format" => {
+                    let name = value()?;
+                    options.palette_format = PaletteFormat::from_name(&name)
+                        .ok_or_else(|| format!("Unknown palette format \"{}\".", name))?;
+                }
+                _ => return Err(format!("Unknown option \"{}\".", arg)),
+            }
+        }
+
+        let command = command.ok_or("Not enough arguments")?;
+        if inputs.is_empty() {
+            return Err("Not enough arguments".to_owned());
+        }
+        if command == Command::Replace && inputs.len() != 3 {
+            return Err("replace needs a BRRES, a texture name and a GCT or PNG.".to_owned());
+        }
+
+        Ok(Args {
+            command,
+            inputs,
+            output,
+            out_dir,
+            layout,
+            tex0_version,
+            mip_levels,
+            mip_filter,
+            format,
+            options,
+            jobs,
+            json,
+            force,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn everything_after_a_double_dash_is_an_input() {
+        let args = parse(&["replace", "--force", "a.brres", "--", "-dash", "new.gct"]).unwrap();
+        let inputs: Vec<&str> = args.inputs.iter().map(|p| p.to_str().unwrap()).collect();
+        assert_eq!(inputs, ["a.brres", "-dash", "new.gct"]);
+        assert!(args.force);
+
+        assert!(parse(&["replace", "a.brres", "-dash", "new.gct"]).is_err());
+        assert!(parse(&["to-gct", "--", "--json"]).is_ok_and(|args| !args.json));
+    }
+}
@@ -0,0 +1,480 @@
+//! CMPR encoding, the GameCube's take on S3TC/DXT1.
+//!
+//! Image data is made of 8x8 tiles, each holding four 4x4 sub-blocks in
+//! left to right, top to bottom order. A sub-block is two big-endian RGB565
+//! endpoints followed by a row byte per line of 2 bit indices, with the
+//! leftmost texel in the highest bits.
+
+use crate::decode::{cmpr_palette, rgb565_to_rgba};
+use crate::encode::rgba_to_rgb565;
+
+/// How hard to search for the endpoints of each sub-block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CmprQuality {
+    /// Range fit: the endpoints are the extremes of the colours along their
+    /// principal axis.
+    Fast,
+    /// Cluster fit: every ordered split of the colours into clusters is
+    /// tried, solving for the best endpoints of each.
+    #[default]
+    High,
+}
+
+impl CmprQuality {
+    pub fn from_name(name: &str) -> Option<CmprQuality> {
+        if name.eq_ignore_ascii_case("fast") {
+            Some(CmprQuality::Fast)
+        } else if name.eq_ignore_ascii_case("high") {
+            Some(CmprQuality::High)
+        } else {
+            None
+        }
+    }
+}
+
+/// Texels with alpha below this are encoded as transparent.
+const ALPHA_THRESHOLD: u8 = 0x80;
+
+/// The weight of the first endpoint in each of the four colours of an
+/// opaque sub-block, matching the hardware's 3/8ths interpolation.
+const OPAQUE_WEIGHTS: [f32; 4] = [1.0, 0.0, 5.0 / 8.0, 3.0 / 8.0];
+
+/// The same for the three colours of a sub-block with transparency.
+const TRANSPARENT_WEIGHTS: [f32; 3] = [1.0, 0.0, 0.5];
+
+pub(crate) fn encode_cmpr(
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    quality: CmprQuality,
+) -> Vec<u8> {
+    let tiles_x = width.div_ceil(8);
+    let tiles_y = height.div_ceil(8);
+
+    let mut data = Vec::with_capacity(tiles_x * tiles_y * 32);
+    for tile_y in 0..tiles_y {
+        for tile_x in 0..tiles_x {
+            for sub_block in 0..4 {
+                let x = tile_x * 8 + (sub_block % 2) * 4;
+                let y = tile_y * 8 + (sub_block / 2) * 4;
+
+                // None for texels past the edges of the image
+                let mut texels = [None; 16];
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    let (tx, ty) = (x + i % 4, y + i / 4);
+                    if tx < width && ty < height {
+                        let p = (ty * width + tx) * 4;
+                        *texel = Some([rgba[p], rgba[p + 1], rgba[p + 2], rgba[p + 3]]);
+                    }
+                }
+
+                data.extend(&encode_sub_block(&texels, quality));
+            }
+        }
+    }
+
+    data
+}
+
+fn encode_sub_block(texels: &[Option<[u8; 4]>; 16], quality: CmprQuality) -> [u8; 8] {
+    let transparent = texels
+        .iter()
+        .any(|t| matches!(t, Some(c) if c[3] < ALPHA_THRESHOLD));
+    let points: Vec<[f32; 3]> = texels
+        .iter()
+        .flatten()
+        .filter(|c| c[3] >= ALPHA_THRESHOLD)
+        .map(|c| [c[0] as f32, c[1] as f32, c[2] as f32])
+        .collect();
+
+    let (mut c0, mut c1) = if points.is_empty() {
+        (0, 0)
+    } else {
+        let weights: &[f32] = if transparent {
+            &TRANSPARENT_WEIGHTS
+        } else {
+            &OPAQUE_WEIGHTS
+        };
+        let (a, b) = range_fit(&points);
+        let mut best = quantize_endpoints(a, b);
+        if quality == CmprQuality::High {
+            if let Some(endpoints) = cluster_fit(&points, weights, best) {
+                best = endpoints;
+            }
+        }
+        best
+    };
+
+    // four colours need c0 > c1, three colours and transparency c0 <= c1
+    if (c0 < c1 && !transparent) || (c0 > c1 && transparent) {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+    let colours = cmpr_palette(c0, c1);
+    let usable = if c0 > c1 { 4 } else { 3 };
+
+    let mut block = [0; 8];
+    block[0..2].copy_from_slice(&c0.to_be_bytes());
+    block[2..4].copy_from_slice(&c1.to_be_bytes());
+    for (i, texel) in texels.iter().enumerate() {
+        let index = match texel {
+            Some(c) if c[3] < ALPHA_THRESHOLD => 3,
+            Some(c) => nearest(&colours[..usable], *c),
+            None => 0,
+        };
+        block[4 + i / 4] |= (index as u8) << (6 - (i % 4) * 2);
+    }
+    block
+}
+
+fn nearest(colours: &[[u8; 4]], c: [u8; 4]) -> usize {
+    let mut best = (0, u32::MAX);
+    for (i, colour) in colours.iter().enumerate() {
+        let error = (0..3)
+            .map(|ch| {
+                let d = colour[ch] as i32 - c[ch] as i32;
+                (d * d) as u32
+            })
+            .sum();
+        if error < best.1 {
+            best = (i, error);
+        }
+    }
+    best.0
+}
+
+/// The principal axis of `points` around their mean, by power iteration on
+/// their covariance.
+fn principal_axis(points: &[[f32; 3]]) -> [f32; 3] {
+    let n = points.len() as f32;
+    let mut mean = [0.0; 3];
+    for p in points {
+        for ch in 0..3 {
+            mean[ch] += p[ch] / n;
+        }
+    }
+
+    let mut covariance = [[0.0_f32; 3]; 3];
+    for p in points {
+        let d = [p[0] - mean[0], p[1] - mean[1], p[2] - mean[2]];
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j];
+            }
+        }
+    }
+
+    let iterate = |mut axis: [f32; 3]| {
+        for _ in 0..8 {
+            let mut next = [0.0; 3];
+            for (i, row) in covariance.iter().enumerate() {
+                next[i] = row[0] * axis[0] + row[1] * axis[1] + row[2] * axis[2];
+            }
+            let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if length < f32::EPSILON {
+                break;
+            }
+            axis = [next[0] / length, next[1] / length, next[2] / length];
+        }
+        axis
+    };
+    // the variance of the points along an axis
+    let spread = |axis: [f32; 3]| {
+        let [x, y, z] = covariance.map(|row| dot(row, axis));
+        dot(axis, [x, y, z]) / dot(axis, axis)
+    };
+
+    // iterating from grey is quickest for most blocks, but it can't reach
+    // an axis at right angles to grey, such as red to blue; the principal
+    // axis always spreads the colours at least as far as the widest channel
+    // does, and under half of that means grey missed it, while a closer
+    // call is left to grey as switching then fits near-flat blocks worse
+    let grey = iterate([1.0, 1.0, 1.0]);
+    let widest = (0..3)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap_or(0);
+    if spread(grey) * 2.0 >= covariance[widest][widest] {
+        return grey;
+    }
+    let mut start = [0.0; 3];
+    start[widest] = 1.0;
+    iterate(start)
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn range_fit(points: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let axis = principal_axis(points);
+    let mut min = (f32::MAX, points[0]);
+    let mut max = (f32::MIN, points[0]);
+    for &p in points {
+        let projection = dot(p, axis);
+        if projection < min.0 {
+            min = (projection, p);
+        }
+        if projection > max.0 {
+            max = (projection, p);
+        }
+    }
+    (max.1, min.1)
+}
+
+fn quantize_endpoints(a: [f32; 3], b: [f32; 3]) -> (u16, u16) {
+    let to_rgba = |c: [f32; 3]| {
+        [
+            c[0].round().clamp(0.0, 255.0) as u8,
+            c[1].round().clamp(0.0, 255.0) as u8,
+            c[2].round().clamp(0.0, 255.0) as u8,
+            0xFF,
+        ]
+    };
+    (rgba_to_rgb565(to_rgba(a)), rgba_to_rgb565(to_rgba(b)))
+}
+
+fn expanded(c: u16) -> [f32; 3] {
+    let rgba = rgb565_to_rgba(c);
+    [rgba[0] as f32, rgba[1] as f32, rgba[2] as f32]
+}
+
+/// Tries every way of splitting the points, ordered along their principal
+/// axis, into consecutive runs that each take one of the colours, returning
+/// the least squares endpoints of the split with the smallest error if it
+/// beats `initial`.
+fn cluster_fit(points: &[[f32; 3]], weights: &[f32], initial: (u16, u16)) -> Option<(u16, u16)> {
+    let axis = principal_axis(points);
+    let mut ordered = points.to_vec();
+    ordered.sort_by(|a, b| dot(*b, axis).total_cmp(&dot(*a, axis)));
+
+    // cluster order along the axis, from the first endpoint to the second
+    let mut order: Vec<f32> = weights.to_vec();
+    order.sort_by(|a, b| b.total_cmp(a));
+
+    let n = ordered.len();
+    let mut prefix = vec![[0.0_f32; 3]; n + 1];
+    for (i, p) in ordered.iter().enumerate() {
+        for ch in 0..3 {
+            prefix[i + 1][ch] = prefix[i][ch] + p[ch];
+        }
+    }
+    let squared: f32 = ordered.iter().map(|p| dot(*p, *p)).sum();
+
+    let error_of = |c0: u16, c1: u16, sums: &Sums| {
+        let (a, b) = (expanded(c0), expanded(c1));
+        squared - 2.0 * (dot(a, sums.ax) + dot(b, sums.bx))
+            + dot(a, a) * sums.aa
+            + 2.0 * dot(a, b) * sums.ab
+            + dot(b, b) * sums.bb
+    };
+
+    let mut best_error = f32::MAX;
+    let mut best = None;
+    let mut try_split = |bounds: &[usize]| {
+        let mut sums = Sums::default();
+        let mut start = 0;
+        for (cluster, &end) in bounds.iter().chain(std::iter::once(&n)).enumerate() {
+            let count = (end - start) as f32;
+            let alpha = order[cluster];
+            let beta = 1.0 - alpha;
+            sums.aa += alpha * alpha * count;
+            sums.ab += alpha * beta * count;
+            sums.bb += beta * beta * count;
+            let (from, to) = (prefix[start], prefix[end]);
+            for ch in 0..3 {
+                sums.ax[ch] += alpha * (to[ch] - from[ch]);
+                sums.bx[ch] += beta * (to[ch] - from[ch]);
+            }
+            start = end;
+        }
+
+        let determinant = sums.aa * sums.bb - sums.ab * sums.ab;
+        if determinant.abs() < f32::EPSILON {
+            return;
+        }
+        let mut a = [0.0; 3];
+        let mut b = [0.0; 3];
+        for ch in 0..3 {
+            a[ch] = (sums.ax[ch] * sums.bb - sums.bx[ch] * sums.ab) / determinant;
+            b[ch] = (sums.bx[ch] * sums.aa - sums.ax[ch] * sums.ab) / determinant;
+        }
+
+        let (c0, c1) = quantize_endpoints(a, b);
+        let error = error_of(c0, c1, &sums);
+        if error < best_error {
+            best_error = error;
+            best = Some((c0, c1));
+        }
+    };
+
+    if order.len() == 4 {
+        for s in 0..=n {
+            for t in s..=n {
+                for u in t..=n {
+                    try_split(&[s, t, u]);
+                }
+            }
+        }
+    } else {
+        for s in 0..=n {
+            for t in s..=n {
+                try_split(&[s, t]);
+            }
+        }
+    }
+
+    // compare against the initial endpoints with each point taking its
+    // nearest colour
+    let initial_error = {
+        let (a, b) = (expanded(initial.0), expanded(initial.1));
+        ordered
+            .iter()
+            .map(|p| {
+                order
+                    .iter()
+                    .map(|w| {
+                        let c = [
+                            a[0] * w + b[0] * (1.0 - w),
+                            a[1] * w + b[1] * (1.0 - w),
+                            a[2] * w + b[2] * (1.0 - w),
+                        ];
+                        let d = [c[0] - p[0], c[1] - p[1], c[2] - p[2]];
+                        dot(d, d)
+                    })
+                    .fold(f32::MAX, f32::min)
+            })
+            .sum::<f32>()
+    };
+
+    if best_error < initial_error {
+        best
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct Sums {
+    aa: f32,
+    ab: f32,
+    bb: f32,
+    ax: [f32; 3],
+    bx: [f32; 3],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, EncodingType};
+
+    const RED: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const CLEAR: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+
+    fn endpoints(block: &[u8; 8]) -> (u16, u16) {
+        (
+            u16::from_be_bytes([block[0], block[1]]),
+            u16::from_be_bytes([block[2], block[3]]),
+        )
+    }
+
+    fn index(block: &[u8; 8], texel: usize) -> u8 {
+        (block[4 + texel / 4] >> (6 - (texel % 4) * 2)) & 0x03
+    }
+
+    /// A sub-block of red and white texels, with every third texel given by
+    /// `third`.
+    fn sub_block(third: [u8; 4]) -> [Option<[u8; 4]>; 16] {
+        let mut texels = [None; 16];
+        for (i, texel) in texels.iter_mut().enumerate() {
+            *texel = Some(if i % 3 == 0 {
+                third
+            } else if i % 2 == 0 {
+                RED
+            } else {
+                WHITE
+            });
+        }
+        texels
+    }
+
+    #[test]
+    fn opaque_sub_blocks_use_four_colours() {
+        for quality in [CmprQuality::Fast, CmprQuality::High] {
+            let block = encode_sub_block(&sub_block(RED), quality);
+            let (c0, c1) = endpoints(&block);
+            assert!(c0 > c1, "{:?}", quality);
+        }
+    }
+
+    #[test]
+    fn transparent_texels_switch_to_three_colours() {
+        for quality in [CmprQuality::Fast, CmprQuality::High] {
+            let texels = sub_block(CLEAR);
+            let block = encode_sub_block(&texels, quality);
+            let (c0, c1) = endpoints(&block);
+            assert!(c0 <= c1, "{:?}", quality);
+
+            let colours = cmpr_palette(c0, c1);
+            for (i, texel) in texels.iter().enumerate() {
+                let index = index(&block, i);
+                match texel {
+                    Some(CLEAR) => assert_eq!(index, 3),
+                    Some(colour) => assert_eq!(colours[index as usize], *colour),
+                    None => unreachable!(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_below_the_threshold_is_transparent() {
+        let mut texels = [Some(RED); 16];
+        texels[5] = Some([0xFF, 0x00, 0x00, ALPHA_THRESHOLD - 1]);
+        texels[6] = Some([0xFF, 0x00, 0x00, ALPHA_THRESHOLD]);
+        let block = encode_sub_block(&texels, CmprQuality::High);
+        assert_eq!(index(&block, 5), 3);
+        assert_ne!(index(&block, 6), 3);
+    }
+
+    #[test]
+    fn fully_transparent_sub_blocks_decode_clear() {
+        let rgba = [CLEAR; 64].concat();
+        let data = encode_cmpr(8, 8, &rgba, CmprQuality::High);
+        let decoded = decode(EncodingType::Cmpr, 8, 8, &data, None).unwrap();
+        assert!(decoded.chunks_exact(4).all(|p| p[3] == 0));
+    }
+
+    #[test]
+    fn transparency_survives_decoding() {
+        // transparent down the left half of the tile only
+        let rgba: Vec<u8> = (0..64)
+            .flat_map(|i| if i % 8 < 4 { CLEAR } else { WHITE })
+            .collect();
+        let data = encode_cmpr(8, 8, &rgba, CmprQuality::High);
+        let decoded = decode(EncodingType::Cmpr, 8, 8, &data, None).unwrap();
+        for (pixel, expected) in decoded.chunks_exact(4).zip(rgba.chunks_exact(4)) {
+            assert_eq!(pixel[3], expected[3]);
+        }
+    }
+
+    #[test]
+    fn red_and_blue_use_red_and_blue_endpoints() {
+        let blue = [0x00, 0x00, 0xFF, 0xFF];
+        let mut texels = [Some(RED); 16];
+        for texel in texels.iter_mut().skip(8) {
+            *texel = Some(blue);
+        }
+        for quality in [CmprQuality::Fast, CmprQuality::High] {
+            let block = encode_sub_block(&texels, quality);
+            let (c0, c1) = endpoints(&block);
+            let mut colours = [c0, c1];
+            colours.sort_unstable();
+            assert_eq!(colours, [0x001F, 0xF800], "{:?}", quality);
+
+            let palette = cmpr_palette(c0, c1);
+            for (i, texel) in texels.iter().enumerate() {
+                assert_eq!(Some(palette[index(&block, i) as usize]), *texel);
+            }
+        }
+    }
+}
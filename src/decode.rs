@@ -0,0 +1,279 @@
+//! Decoding of the tiled GameCube/Wii texture encodings to plain RGBA8.
+
+use crate::{EncodingType, GctconvError, PaletteFormat};
+
+/// Decodes `data` to a `width` by `height` RGBA8 buffer, four bytes per
+/// pixel in rows from the top left.
+///
+/// Palette encodings need `palette`, the palette format and its data.
+pub fn decode(
+    encoding: EncodingType,
+    width: u16,
+    height: u16,
+    data: &[u8],
+    palette: Option<(PaletteFormat, &[u8])>,
+) -> Result<Vec<u8>, GctconvError> {
+    let (width, height) = (width as usize, height as usize);
+    let needed = encoding.image_size(width, height);
+    if data.len() < needed {
+        return Err(GctconvError::DataTooShort {
+            needed,
+            found: data.len(),
+        });
+    }
+    if encoding.has_palette() && palette.is_none() {
+        return Err(GctconvError::MissingPalette);
+    }
+
+    let block_width = encoding.block_width();
+    let block_height = encoding.block_height();
+    let block_size = block_width * block_height * encoding.bits_per_pixel() / 8;
+    let blocks_x = width.div_ceil(block_width);
+
+    let mut rgba = vec![0; width * height * 4];
+    for (block_index, block) in data[..needed].chunks_exact(block_size).enumerate() {
+        let block_x = (block_index % blocks_x) * block_width;
+        let block_y = (block_index / blocks_x) * block_height;
+
+        for texel in 0..block_width * block_height {
+            let x = block_x + texel % block_width;
+            let y = block_y + texel / block_width;
+            if x >= width || y >= height {
+                continue;
+            }
+
+            let colour = match encoding {
+                EncodingType::Cmpr => cmpr_texel(block, texel),
+                EncodingType::Rgba32 => rgba32_texel(block, texel),
+                _ => texel_colour(encoding, texel_value(encoding, block, texel), palette),
+            };
+            let i = (y * width + x) * 4;
+            rgba[i..i + 4].copy_from_slice(&colour);
+        }
+    }
+
+    Ok(rgba)
+}
+
+/// Reads the raw 4, 8 or 16 bit value of a texel within its tile.
+fn texel_value(encoding: EncodingType, block: &[u8], texel: usize) -> u16 {
+    match encoding.bits_per_pixel() {
+        4 => {
+            let byte = block[texel / 2];
+            if texel.is_multiple_of(2) {
+                (byte >> 4) as u16
+            } else {
+                (byte & 0x0F) as u16
+            }
+        }
+        8 => block[texel] as u16,
+        _ => u16::from_be_bytes([block[texel * 2], block[texel * 2 + 1]]),
+    }
+}
+
+/// Decodes the value of a texel of any encoding but RGBA32 and CMPR.
+pub(crate) fn texel_colour(
+    encoding: EncodingType,
+    value: u16,
+    palette: Option<(PaletteFormat, &[u8])>,
+) -> [u8; 4] {
+    match encoding {
+        EncodingType::I4 => {
+            let i = expand4(value as u8);
+            [i, i, i, i]
+        }
+        EncodingType::I8 => {
+            let i = value as u8;
+            [i, i, i, i]
+        }
+        EncodingType::Ia4 => {
+            let i = expand4(value as u8 & 0x0F);
+            [i, i, i, expand4(value as u8 >> 4)]
+        }
+        EncodingType::Ia8 => ia8_to_rgba(value),
+        EncodingType::Rgb565 => rgb565_to_rgba(value),
+        EncodingType::Rgb5A3 => rgb5a3_to_rgba(value),
+        EncodingType::Ci4 | EncodingType::Ci8 | EncodingType::Ci14x2 => {
+            let (format, data) = match palette {
+                Some(p) => p,
+                None => return [0; 4],
+            };
+            let index = (value & 0x3FFF) as usize * 2;
+            match data.get(index..index + 2) {
+                Some(entry) => palette_colour(format, u16::from_be_bytes([entry[0], entry[1]])),
+                // indices past the end of a short palette are transparent
+                None => [0; 4],
+            }
+        }
+        EncodingType::Rgba32 | EncodingType::Cmpr => unreachable!(),
+    }
+}
+
+/// Decodes a single palette entry.
+pub(crate) fn palette_colour(format: PaletteFormat, entry: u16) -> [u8; 4] {
+    match format {
+        PaletteFormat::Ia8 => ia8_to_rgba(entry),
+        PaletteFormat::Rgb565 => rgb565_to_rgba(entry),
+        PaletteFormat::Rgb5A3 => rgb5a3_to_rgba(entry),
+    }
+}
+
+/// RGBA32 tiles store the alpha and red of all 16 texels, then the green
+/// and blue.
+fn rgba32_texel(block: &[u8], texel: usize) -> [u8; 4] {
+    let ar = &block[texel * 2..texel * 2 + 2];
+    let gb = &block[0x20 + texel * 2..0x20 + texel * 2 + 2];
+    [ar[1], gb[0], gb[1], ar[0]]
+}
+
+/// CMPR tiles are 8x8, made of four 4x4 DXT1 sub-blocks of 8 bytes each in
+/// left to right, top to bottom order.
+fn cmpr_texel(block: &[u8], texel: usize) -> [u8; 4] {
+    let (x, y) = (texel % 8, texel / 8);
+    let sub_block = &block[((y / 4) * 2 + x / 4) * 8..][..8];
+    let colours = cmpr_palette(
+        u16::from_be_bytes([sub_block[0], sub_block[1]]),
+        u16::from_be_bytes([sub_block[2], sub_block[3]]),
+    );
+
+    // 2 bit indices, first texel in the highest bits
+    let row = sub_block[4 + y % 4];
+    let index = (row >> (6 - (x % 4) * 2)) & 0x03;
+    colours[index as usize]
+}
+
+/// The four colours a CMPR sub-block can pick from.
+///
+/// The hardware interpolates in 3/8ths rather than thirds, and when the
+/// first endpoint isn't greater than the second the last colour is
+/// transparent instead.
+pub(crate) fn cmpr_palette(c0: u16, c1: u16) -> [[u8; 4]; 4] {
+    let a = rgb565_to_rgba(c0);
+    let b = rgb565_to_rgba(c1);
+    let mut colours = [a, b, [0; 4], [0; 4]];
+    for channel in 0..3 {
+        let (ca, cb) = (a[channel] as u32, b[channel] as u32);
+        if c0 > c1 {
+            colours[2][channel] = ((ca * 5 + cb * 3) >> 3) as u8;
+            colours[3][channel] = ((ca * 3 + cb * 5) >> 3) as u8;
+        } else {
+            colours[2][channel] = ((ca + cb) / 2) as u8;
+            colours[3][channel] = colours[2][channel];
+        }
+    }
+    colours[2][3] = 0xFF;
+    colours[3][3] = if c0 > c1 { 0xFF } else { 0x00 };
+    colours
+}
+
+/// IA8 stores alpha in the first byte and intensity in the second.
+pub(crate) fn ia8_to_rgba(value: u16) -> [u8; 4] {
+    let i = value as u8;
+    [i, i, i, (value >> 8) as u8]
+}
+
+pub(crate) fn rgb565_to_rgba(value: u16) -> [u8; 4] {
+    [
+        expand5((value >> 11) as u8 & 0x1F),
+        expand6((value >> 5) as u8 & 0x3F),
+        expand5(value as u8 & 0x1F),
+        0xFF,
+    ]
+}
+
+/// RGB5A3 is RGB555 when the top bit is set, and ARGB3444 otherwise.
+pub(crate) fn rgb5a3_to_rgba(value: u16) -> [u8; 4] {
+    if value & 0x8000 != 0 {
+        [
+            expand5((value >> 10) as u8 & 0x1F),
+            expand5((value >> 5) as u8 & 0x1F),
+            expand5(value as u8 & 0x1F),
+            0xFF,
+        ]
+    } else {
+        [
+            expand4((value >> 8) as u8 & 0x0F),
+            expand4((value >> 4) as u8 & 0x0F),
+            expand4(value as u8 & 0x0F),
+            expand3((value >> 12) as u8 & 0x07),
+        ]
+    }
+}
+
+fn expand3(v: u8) -> u8 {
+    (v << 5) | (v << 2) | (v >> 1)
+}
+
+fn expand4(v: u8) -> u8 {
+    (v << 4) | v
+}
+
+fn expand5(v: u8) -> u8 {
+    (v << 3) | (v >> 2)
+}
+
+fn expand6(v: u8) -> u8 {
+    (v << 2) | (v >> 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_go_left_to_right_then_down() {
+        // I8 tiles are 8x4, so a 16x8 image is two tiles across and two down
+        let data: Vec<u8> = (0..=0x7F).collect();
+        let rgba = decode(EncodingType::I8, 16, 8, &data, None).unwrap();
+        for (y, row) in rgba.chunks_exact(16 * 4).enumerate() {
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                let tile = (y / 4) * 2 + x / 8;
+                let texel = (y % 4) * 8 + x % 8;
+                assert_eq!(pixel[0] as usize, tile * 32 + texel, "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn partial_tiles_are_cropped() {
+        // a 5x3 I4 image still takes a whole 8x8 tile
+        let data: Vec<u8> = (0..32).map(|i| (i * 0x11) as u8).collect();
+        let rgba = decode(EncodingType::I4, 5, 3, &data, None).unwrap();
+        assert_eq!(rgba.len(), 5 * 3 * 4);
+        // the first texel of the second row is the ninth of the tile
+        assert_eq!(rgba[5 * 4], expand4(data[4] >> 4));
+    }
+
+    #[test]
+    fn rgba32_splits_tiles_in_two() {
+        let mut data = vec![0; 64];
+        // alpha and red of the second texel, then its green and blue
+        data[2..4].copy_from_slice(&[0x80, 0x11]);
+        data[0x22..0x24].copy_from_slice(&[0x22, 0x33]);
+        let rgba = decode(EncodingType::Rgba32, 4, 4, &data, None).unwrap();
+        assert_eq!(rgba[4..8], [0x11, 0x22, 0x33, 0x80]);
+    }
+
+    #[test]
+    fn palette_encodings_need_a_palette() {
+        let result = decode(EncodingType::Ci8, 8, 4, &[0; 32], None);
+        assert!(matches!(result, Err(GctconvError::MissingPalette)));
+    }
+
+    #[test]
+    fn short_palettes_are_transparent_past_the_end() {
+        let data = [0, 1, 2, 3].repeat(8);
+        let palette = [0xFF, 0xFF, 0x80, 0x00];
+        let rgba = decode(
+            EncodingType::Ci8,
+            8,
+            4,
+            &data,
+            Some((PaletteFormat::Rgb5A3, &palette)),
+        )
+        .unwrap();
+        assert_eq!(rgba[..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(rgba[4..8], [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(rgba[8..16], [0; 8]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Dithering for the encodings with few bits per channel, trading banding in
+//! gradients for a fine pattern of the nearest colours either side.
+
+/// How an image is dithered as it is quantized to an encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Every pixel is rounded to the nearest colour the encoding has.
+    #[default]
+    None,
+    /// Error diffusion spreading all of each pixel's rounding error to its
+    /// neighbours, for the smoothest gradients.
+    FloydSteinberg,
+    /// Error diffusion spreading three quarters of the error further out,
+    /// which keeps more contrast at the cost of some detail in the darks and
+    /// lights.
+    Atkinson,
+    /// An ordered 4x4 Bayer pattern. Its regular grid doesn't shift around
+    /// as the image changes, so it suits textures that are edited often.
+    Bayer,
+}
+
+const DITHER_NAMES: [(&str, Dither); 4] = [
+    ("none", Dither::None),
+    ("floyd-steinberg", Dither::FloydSteinberg),
+    ("atkinson", Dither::Atkinson),
+    ("bayer", Dither::Bayer),
+];
+
+/// Where each error diffusion passes a pixel's error on to, as x and y
+/// offsets and the share of the error, going left to right.
+const FLOYD_STEINBERG: [(isize, usize, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: [(isize, usize, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Dither {
+    pub fn from_name(name: &str) -> Option<Dither> {
+        DITHER_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, d)| *d)
+    }
+
+    pub fn name(self) -> &'static str {
+        match DITHER_NAMES.iter().find(|(_, d)| *d == self) {
+            Some((n, _)) => n,
+            None => unreachable!(),
+        }
+    }
+}
+
+/// The Bayer threshold for a pixel, from just under -0.5 to just under 0.5.
+fn threshold(x: usize, y: usize) -> f32 {
+    (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5
+}
+
+/// Quantizes a `width` by `height` RGBA8 image, four bytes per pixel, with
+/// `dither`, returning the texel value of every pixel.
+///
+/// `quantize` gives the value that stores a colour and the colour that
+/// value decodes to. `steps` gives the distance between neighbouring
+/// levels of each channel when the pixel has the given alpha, or 0 for
+/// channels that aren't dithered because they aren't stored or are stored
+/// in full.
+pub(crate) fn dither<Q, S>(
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    dither: Dither,
+    mut quantize: Q,
+    steps: S,
+) -> Vec<u16>
+where
+    Q: FnMut([u8; 4]) -> (u16, [u8; 4]),
+    S: Fn(u8) -> [f32; 4],
+{
+    let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    let kernel: &[(isize, usize, f32)] = match dither {
+        Dither::None => {
+            return rgba
+                .chunks_exact(4)
+                .map(|p| quantize([p[0], p[1], p[2], p[3]]).0)
+                .collect()
+        }
+        Dither::Bayer => {
+            let mut values = Vec::with_capacity(width * height);
+            for (i, p) in rgba.chunks_exact(4).enumerate() {
+                let (x, y) = (i % width, i / width);
+                // alpha gets the pattern on its side, so it doesn't line up
+                // with the colour's, and picks the colour steps first
+                let alpha = clamp(p[3] as f32 + threshold(y, x) * steps(p[3])[3]);
+                let step = steps(alpha);
+                let mut colour = [0; 4];
+                for ch in 0..3 {
+                    colour[ch] = clamp(p[ch] as f32 + threshold(x, y) * step[ch]);
+                }
+                colour[3] = alpha;
+                values.push(quantize(colour).0);
+            }
+            return values;
+        }
+        Dither::FloydSteinberg => &FLOYD_STEINBERG,
+        Dither::Atkinson => &ATKINSON,
+    };
+
+    let mut wanted: Vec<f32> = rgba.iter().map(|&v| v as f32).collect();
+    let mut values = vec![0; width * height];
+    for y in 0..height {
+        // every other row goes right to left, so the error doesn't drift
+        // one way
+        let reverse = y % 2 == 1;
+        for i in 0..width {
+            let x = if reverse { width - 1 - i } else { i };
+            let at = (y * width + x) * 4;
+            let mut colour = [0; 4];
+            for ch in 0..4 {
+                colour[ch] = clamp(wanted[at + ch]);
+            }
+            let (value, got) = quantize(colour);
+            values[y * width + x] = value;
+
+            // each channel, alpha included, carries its own error
+            let step = steps(got[3]);
+            for ch in 0..4 {
+                if step[ch] == 0.0 {
+                    continue;
+                }
+                let error = colour[ch] as f32 - got[ch] as f32;
+                for &(dx, dy, share) in kernel {
+                    let dx = if reverse { -dx } else { dx };
+                    let (nx, ny) = (x as isize + dx, y + dy);
+                    if nx < 0 || nx as usize >= width || ny >= height {
+                        continue;
+                    }
+                    wanted[(ny * width + nx as usize) * 4 + ch] += error * share;
+                }
+            }
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode::rgb5a3_to_rgba, encode::rgba_to_rgb5a3};
+
+    const SIZE: usize = 16;
+
+    fn rgb5a3(colour: [u8; 4]) -> (u16, [u8; 4]) {
+        let value = rgba_to_rgb5a3(colour);
+        (value, rgb5a3_to_rgba(value))
+    }
+
+    /// RGB5A3's steps while opaque: 5 bits of colour, 3 of alpha.
+    fn rgb5a3_steps(_: u8) -> [f32; 4] {
+        [255.0 / 31.0, 255.0 / 31.0, 255.0 / 31.0, 255.0 / 7.0]
+    }
+
+    /// Rounds each channel to 4 bits, packing them into the value.
+    fn nibbles(colour: [u8; 4]) -> (u16, [u8; 4]) {
+        let mut value = 0;
+        let mut got = [0; 4];
+        for ch in 0..4 {
+            let v = (colour[ch] as u16 + 8) / 17;
+            value |= v << (ch * 4);
+            got[ch] = v as u8 * 17;
+        }
+        (value, got)
+    }
+
+    fn unpack(value: u16) -> [u8; 4] {
+        [0, 1, 2, 3].map(|ch| ((value >> (ch * 4)) & 0xF) as u8 * 17)
+    }
+
+    fn flat(colour: [u8; 4]) -> Vec<u8> {
+        colour.repeat(SIZE * SIZE)
+    }
+
+    fn mean(colours: &[[u8; 4]], ch: usize) -> f32 {
+        colours.iter().map(|c| c[ch] as f32).sum::<f32>() / colours.len() as f32
+    }
+
+    #[test]
+    fn error_diffusion_uses_the_encodings_levels() {
+        // 103 falls between RGB5A3's levels 12 (99) and 13 (107)
+        let rgba = flat([103, 103, 103, 255]);
+        for method in [Dither::FloydSteinberg, Dither::Atkinson] {
+            let values = dither(SIZE, SIZE, &rgba, method, rgb5a3, rgb5a3_steps);
+            let colours: Vec<[u8; 4]> = values.into_iter().map(rgb5a3_to_rgba).collect();
+            for colour in &colours {
+                assert!(
+                    matches!(colour[0], 99 | 107),
+                    "{:?} with {:?}",
+                    colour,
+                    method
+                );
+                assert_eq!(colour[3], 255);
+            }
+            assert!(colours.iter().any(|c| c[0] == 99));
+            assert!(colours.iter().any(|c| c[0] == 107));
+        }
+
+        let values = dither(
+            SIZE,
+            SIZE,
+            &rgba,
+            Dither::FloydSteinberg,
+            rgb5a3,
+            rgb5a3_steps,
+        );
+        let colours: Vec<[u8; 4]> = values.into_iter().map(rgb5a3_to_rgba).collect();
+        assert!((mean(&colours, 0) - 103.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn alpha_is_diffused_on_its_own() {
+        let steps = |_| [17.0; 4];
+
+        // the colour is stored exactly, so only alpha is dithered
+        let rgba = flat([34, 34, 34, 8]);
+        let values = dither(SIZE, SIZE, &rgba, Dither::FloydSteinberg, nibbles, steps);
+        let colours: Vec<[u8; 4]> = values.into_iter().map(unpack).collect();
+        assert!(colours.iter().all(|c| c[..3] == [34, 34, 34]));
+        assert!(colours.iter().all(|c| matches!(c[3], 0 | 17)));
+        assert!((mean(&colours, 3) - 8.0).abs() < 1.0);
+
+        // and the colour's error never reaches alpha
+        let rgba = flat([8, 8, 8, 255]);
+        let values = dither(SIZE, SIZE, &rgba, Dither::FloydSteinberg, nibbles, steps);
+        let colours: Vec<[u8; 4]> = values.into_iter().map(unpack).collect();
+        assert!(colours.iter().all(|c| c[3] == 255));
+        assert!((mean(&colours, 0) - 8.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn bayer_is_deterministic() {
+        let rgba: Vec<u8> = (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let v = (i * 255 / (SIZE * SIZE)) as u8;
+                [v, v / 2, 255 - v, 255]
+            })
+            .collect();
+        let first = dither(SIZE, SIZE, &rgba, Dither::Bayer, rgb5a3, rgb5a3_steps);
+        let second = dither(SIZE, SIZE, &rgba, Dither::Bayer, rgb5a3, rgb5a3_steps);
+        assert_eq!(first, second);
+
+        // a flat colour gets the same 4x4 pattern everywhere
+        let rgba = flat([103, 103, 103, 255]);
+        let values = dither(SIZE, SIZE, &rgba, Dither::Bayer, rgb5a3, rgb5a3_steps);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                assert_eq!(values[y * SIZE + x], values[(y % 4) * SIZE + x % 4]);
+            }
+        }
+        assert!(values[..4].iter().any(|&v| v != values[0]));
+    }
+}
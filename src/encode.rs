@@ -0,0 +1,428 @@
+//! Encoding of plain RGBA8 images into the tiled GameCube/Wii texture
+//! encodings.
+
+use crate::{
+    cmpr::encode_cmpr,
+    decode::{palette_colour, texel_colour},
+    dither::dither,
+    palette::{build_palette, distance, PaletteSearch},
+    CmprQuality, Dither, EncodingType, Filter, Fit, GctconvError, PaletteFormat,
+};
+
+/// Settings for the encodings that have to make choices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub cmpr_quality: CmprQuality,
+    /// The format palettes of colour index encodings are built in.
+    pub palette_format: PaletteFormat,
+    /// How `Gct::from_rgba` fits images to power of two sizes, if at all.
+    pub fit: Option<Fit>,
+    /// The filter images are scaled with when fitting them.
+    pub fit_filter: Filter,
+    /// How the encodings with few bits per channel, and the colour index
+    /// encodings, are dithered.
+    pub dither: Dither,
+}
+
+/// Encodes a `width` by `height` RGBA8 buffer, four bytes per pixel in rows
+/// from the top left, into tiled image data, returning the image data and,
+/// for colour index encodings, the palette data.
+///
+/// Texels in the padding past the right and bottom edges are left
+/// transparent black.
+pub fn encode(
+    encoding: EncodingType,
+    width: u16,
+    height: u16,
+    rgba: &[u8],
+    options: &EncodeOptions,
+) -> Result<(Vec<u8>, Vec<u8>), GctconvError> {
+    let (width, height) = (width as usize, height as usize);
+    let needed = width * height * 4;
+    if rgba.len() < needed {
+        return Err(GctconvError::DataTooShort {
+            needed,
+            found: rgba.len(),
+        });
+    }
+    let rgba = &rgba[..needed];
+
+    match encoding {
+        EncodingType::Cmpr => {
+            let image_data = encode_cmpr(width, height, rgba, options.cmpr_quality);
+            Ok((image_data, Vec::new()))
+        }
+        EncodingType::Ci4 | EncodingType::Ci8 | EncodingType::Ci14x2 => {
+            let colours = encoding.palette_size() / 2;
+            let (palette, mut indices) = build_palette(rgba, colours, options.palette_format);
+            if options.dither != Dither::None {
+                // dithered against the palette built from the image as it is
+                let decoded: Vec<[u8; 4]> = palette
+                    .iter()
+                    .map(|&entry| palette_colour(options.palette_format, entry))
+                    .collect();
+                let step = palette_step(&decoded);
+                let mut search = PaletteSearch::new(&decoded);
+                indices = dither(
+                    width,
+                    height,
+                    rgba,
+                    options.dither,
+                    |colour| {
+                        let index = search.nearest(colour);
+                        (index as u16, decoded[index])
+                    },
+                    |_| [step; 4],
+                );
+            }
+
+            let image_data = tile(encoding, width, height, |i| indices[i]);
+            let mut palette_data: Vec<u8> = palette.iter().flat_map(|e| e.to_be_bytes()).collect();
+            palette_data.resize(encoding.palette_size(), 0);
+            Ok((image_data, palette_data))
+        }
+        EncodingType::Rgba32 => Ok((encode_rgba32(width, height, rgba), Vec::new())),
+        _ => {
+            // the intensity encodings are dithered in grey, not each channel
+            let grey: Vec<u8>;
+            let rgba = if matches!(encoding, EncodingType::I4 | EncodingType::Ia4) {
+                grey = rgba
+                    .chunks_exact(4)
+                    .flat_map(|p| {
+                        let i = intensity([p[0], p[1], p[2], p[3]]);
+                        [i, i, i, p[3]]
+                    })
+                    .collect();
+                &grey[..]
+            } else {
+                rgba
+            };
+            let values = dither(
+                width,
+                height,
+                rgba,
+                options.dither,
+                |colour| {
+                    let value = texel_value(encoding, colour);
+                    (value, texel_colour(encoding, value, None))
+                },
+                |alpha| dither_steps(encoding, alpha),
+            );
+            let image_data = tile(encoding, width, height, |i| values[i]);
+            Ok((image_data, Vec::new()))
+        }
+    }
+}
+
+/// Encodes an image in a colour index encoding against an existing palette
+/// of decoded colours, picking the nearest entry for every pixel.
+pub(crate) fn encode_indexed(
+    encoding: EncodingType,
+    width: u16,
+    height: u16,
+    rgba: &[u8],
+    palette: &[[u8; 4]],
+) -> Vec<u8> {
+    let mut search = PaletteSearch::new(palette);
+    let indices: Vec<u16> = rgba
+        .chunks_exact(4)
+        .map(|p| search.nearest([p[0], p[1], p[2], p[3]]) as u16)
+        .collect();
+    tile(encoding, width as usize, height as usize, |i| indices[i])
+}
+
+/// Lays out the 4, 8 or 16 bit texel values given by `value_of`, which is
+/// passed the index of each pixel, into tiles.
+fn tile<F>(encoding: EncodingType, width: usize, height: usize, value_of: F) -> Vec<u8>
+where
+    F: Fn(usize) -> u16,
+{
+    let block_width = encoding.block_width();
+    let block_height = encoding.block_height();
+    let block_size = block_width * block_height * encoding.bits_per_pixel() / 8;
+    let blocks_x = width.div_ceil(block_width);
+
+    let mut data = vec![0; encoding.image_size(width, height)];
+    for (block_index, block) in data.chunks_exact_mut(block_size).enumerate() {
+        let block_x = (block_index % blocks_x) * block_width;
+        let block_y = (block_index / blocks_x) * block_height;
+
+        for texel in 0..block_width * block_height {
+            let x = block_x + texel % block_width;
+            let y = block_y + texel / block_width;
+            if x < width && y < height {
+                set_texel_value(encoding, block, texel, value_of(y * width + x));
+            }
+        }
+    }
+
+    data
+}
+
+/// RGBA32 tiles store the alpha and red of all 16 texels, then the green
+/// and blue.
+fn encode_rgba32(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let blocks_x = width.div_ceil(4);
+
+    let mut data = vec![0; EncodingType::Rgba32.image_size(width, height)];
+    for (block_index, block) in data.chunks_exact_mut(64).enumerate() {
+        let block_x = (block_index % blocks_x) * 4;
+        let block_y = (block_index / blocks_x) * 4;
+
+        for texel in 0..16 {
+            let x = block_x + texel % 4;
+            let y = block_y + texel / 4;
+            if x < width && y < height {
+                let p = &rgba[(y * width + x) * 4..][..4];
+                block[texel * 2] = p[3];
+                block[texel * 2 + 1] = p[0];
+                block[0x20 + texel * 2] = p[1];
+                block[0x20 + texel * 2 + 1] = p[2];
+            }
+        }
+    }
+
+    data
+}
+
+/// Writes the raw 4, 8 or 16 bit value of a texel within its tile.
+fn set_texel_value(encoding: EncodingType, block: &mut [u8], texel: usize, value: u16) {
+    match encoding.bits_per_pixel() {
+        4 => {
+            if texel.is_multiple_of(2) {
+                block[texel / 2] |= (value as u8 & 0x0F) << 4;
+            } else {
+                block[texel / 2] |= value as u8 & 0x0F;
+            }
+        }
+        8 => block[texel] = value as u8,
+        _ => block[texel * 2..texel * 2 + 2].copy_from_slice(&value.to_be_bytes()),
+    }
+}
+
+/// The distance between neighbouring levels of the red, green, blue and
+/// alpha an encoding stores, for a pixel of `alpha`; 0 for channels that
+/// aren't stored or are stored in full 8 bits.
+fn dither_steps(encoding: EncodingType, alpha: u8) -> [f32; 4] {
+    let step = |bits: u32| 255.0 / ((1 << bits) - 1) as f32;
+    match encoding {
+        EncodingType::I4 => [step(4), step(4), step(4), 0.0],
+        EncodingType::Ia4 => [step(4); 4],
+        EncodingType::Rgb565 => [step(5), step(6), step(5), 0.0],
+        // RGB555 when opaque, ARGB3444 otherwise
+        EncodingType::Rgb5A3 if quantize(alpha, 3) == 7 => [step(5), step(5), step(5), step(3)],
+        EncodingType::Rgb5A3 => [step(4), step(4), step(4), step(3)],
+        _ => [0.0; 4],
+    }
+}
+
+/// A rough distance between neighbouring colours of a palette: how far,
+/// on average, each entry is from the one nearest it. CI14x2 palettes are
+/// too large to compare every pair, so only some of their entries are
+/// measured.
+fn palette_step(palette: &[[u8; 4]]) -> f32 {
+    if palette.len() < 2 {
+        return 0.0;
+    }
+    let measured = palette
+        .iter()
+        .enumerate()
+        .step_by((palette.len() / 0x100).max(1));
+    let distances: Vec<f32> = measured
+        .map(|(i, &entry)| {
+            let nearest = palette
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, &other)| distance(entry, other))
+                .min()
+                .unwrap_or(0);
+            (nearest as f32).sqrt()
+        })
+        .collect();
+    distances.iter().sum::<f32>() / distances.len() as f32
+}
+
+fn texel_value(encoding: EncodingType, colour: [u8; 4]) -> u16 {
+    match encoding {
+        EncodingType::I4 => quantize(intensity(colour), 4) as u16,
+        EncodingType::I8 => intensity(colour) as u16,
+        EncodingType::Ia4 => {
+            ((quantize(colour[3], 4) << 4) | quantize(intensity(colour), 4)) as u16
+        }
+        EncodingType::Ia8 => rgba_to_ia8(colour),
+        EncodingType::Rgb565 => rgba_to_rgb565(colour),
+        EncodingType::Rgb5A3 => rgba_to_rgb5a3(colour),
+        _ => unreachable!(),
+    }
+}
+
+/// Encodes a single palette entry.
+pub(crate) fn palette_entry(format: PaletteFormat, colour: [u8; 4]) -> u16 {
+    match format {
+        PaletteFormat::Ia8 => rgba_to_ia8(colour),
+        PaletteFormat::Rgb565 => rgba_to_rgb565(colour),
+        PaletteFormat::Rgb5A3 => rgba_to_rgb5a3(colour),
+    }
+}
+
+/// Rec. 601 luma, used for the intensity encodings.
+pub(crate) fn intensity(colour: [u8; 4]) -> u8 {
+    let [r, g, b, _] = colour;
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8
+}
+
+/// Rounds an 8 bit channel to the nearest `bits` bit value.
+pub(crate) fn quantize(v: u8, bits: u32) -> u8 {
+    let max = (1_u32 << bits) - 1;
+    ((v as u32 * max + 127) / 255) as u8
+}
+
+/// IA8 stores alpha in the first byte and intensity in the second.
+pub(crate) fn rgba_to_ia8(colour: [u8; 4]) -> u16 {
+    ((colour[3] as u16) << 8) | intensity(colour) as u16
+}
+
+pub(crate) fn rgba_to_rgb565(colour: [u8; 4]) -> u16 {
+    ((quantize(colour[0], 5) as u16) << 11)
+        | ((quantize(colour[1], 6) as u16) << 5)
+        | quantize(colour[2], 5) as u16
+}
+
+/// RGB5A3 is RGB555 for opaque colours, and ARGB3444 for anything with
+/// alpha that survives the 3 bit quantization.
+pub(crate) fn rgba_to_rgb5a3(colour: [u8; 4]) -> u16 {
+    let alpha = quantize(colour[3], 3);
+    if alpha == 7 {
+        0x8000
+            | ((quantize(colour[0], 5) as u16) << 10)
+            | ((quantize(colour[1], 5) as u16) << 5)
+            | quantize(colour[2], 5) as u16
+    } else {
+        ((alpha as u16) << 12)
+            | ((quantize(colour[0], 4) as u16) << 8)
+            | ((quantize(colour[1], 4) as u16) << 4)
+            | quantize(colour[2], 4) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, decode::rgb565_to_rgba, decode::rgb5a3_to_rgba};
+
+    /// Not a whole number of tiles in any encoding.
+    const WIDTH: u16 = 10;
+    const HEIGHT: u16 = 6;
+
+    fn pixels() -> impl Iterator<Item = usize> {
+        0..WIDTH as usize * HEIGHT as usize
+    }
+
+    /// Encodes and decodes `rgba`, which has to be made of colours the
+    /// encoding stores exactly.
+    fn round_trip(encoding: EncodingType, rgba: &[u8]) -> Vec<u8> {
+        let options = EncodeOptions::default();
+        let (data, palette) = encode(encoding, WIDTH, HEIGHT, rgba, &options).unwrap();
+        assert_eq!(
+            data.len(),
+            encoding.image_size(WIDTH as usize, HEIGHT as usize)
+        );
+        let palette = match encoding.has_palette() {
+            true => Some((options.palette_format, &palette[..])),
+            false => None,
+        };
+        decode(encoding, WIDTH, HEIGHT, &data, palette).unwrap()
+    }
+
+    /// An image of colours `encoding` stores exactly, decoded from made up
+    /// image data.
+    fn stored_colours(encoding: EncodingType) -> Vec<u8> {
+        let size = encoding.image_size(WIDTH as usize, HEIGHT as usize);
+        let mut data: Vec<u8> = (0..size).map(|i| (i * 0x9D + 0x31) as u8).collect();
+        if encoding == EncodingType::Rgb5A3 {
+            // fully opaque ARGB3444 is stored as RGB555 instead
+            for value in data.chunks_exact_mut(2) {
+                if value[0] & 0xF0 == 0x70 {
+                    value[0] |= 0x80;
+                }
+            }
+        }
+        decode(encoding, WIDTH, HEIGHT, &data, None).unwrap()
+    }
+
+    #[test]
+    fn direct_encodings_round_trip() {
+        let encodings = [
+            EncodingType::I4,
+            EncodingType::I8,
+            EncodingType::Ia4,
+            EncodingType::Ia8,
+            EncodingType::Rgb565,
+            EncodingType::Rgb5A3,
+            EncodingType::Rgba32,
+        ];
+        for encoding in encodings {
+            let rgba = stored_colours(encoding);
+            assert_eq!(round_trip(encoding, &rgba), rgba, "{}", encoding.name());
+        }
+    }
+
+    #[test]
+    fn colour_index_encodings_round_trip() {
+        // few enough colours for every palette, all exact in RGB5A3: half
+        // opaque, half with an alpha below opaque
+        let colours: Vec<[u8; 4]> = (0..12_u16)
+            .map(|i| {
+                let value = i * 0x0A53;
+                match i % 2 {
+                    0 => rgb5a3_to_rgba(value | 0x8000),
+                    _ => rgb5a3_to_rgba(value & 0x6FFF),
+                }
+            })
+            .collect();
+        let rgba: Vec<u8> = pixels()
+            .flat_map(|i| colours[(i * 5) % colours.len()])
+            .collect();
+        for encoding in [EncodingType::Ci4, EncodingType::Ci8, EncodingType::Ci14x2] {
+            assert_eq!(round_trip(encoding, &rgba), rgba, "{}", encoding.name());
+        }
+    }
+
+    #[test]
+    fn indexed_encoding_picks_nearest_entries() {
+        let palette = [[0, 0, 0, 255], [255, 255, 255, 255], [255, 0, 0, 255]];
+        let rgba: Vec<u8> = pixels().flat_map(|i| [(i * 4) as u8, 0, 0, 255]).collect();
+        let data = encode_indexed(EncodingType::Ci8, WIDTH, HEIGHT, &rgba, &palette);
+        let decoded = decode(
+            EncodingType::Ci8,
+            WIDTH,
+            HEIGHT,
+            &data,
+            Some((PaletteFormat::Rgb565, &[0x00, 0x00, 0xFF, 0xFF, 0xF8, 0x00])),
+        )
+        .unwrap();
+        for (pixel, got) in rgba.chunks_exact(4).zip(decoded.chunks_exact(4)) {
+            let expected = if pixel[0] < 128 {
+                [0, 0, 0, 255]
+            } else {
+                [255, 0, 0, 255]
+            };
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn cmpr_round_trips() {
+        // two RGB565 colours in every 4x4 sub-block are its endpoints
+        let rgba: Vec<u8> = pixels()
+            .flat_map(|i| {
+                let (x, y) = (i % WIDTH as usize, i / WIDTH as usize);
+                let sub_block = (y / 4 * 3 + x / 4) as u16;
+                let value = if (x + y) % 3 == 0 { 0xF800 } else { 0x001F };
+                rgb565_to_rgba(value ^ (sub_block * 0x0841))
+            })
+            .collect();
+        assert_eq!(round_trip(EncodingType::Cmpr, &rgba), rgba);
+    }
+}
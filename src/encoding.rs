@@ -0,0 +1,170 @@
+use num_traits::FromPrimitive;
+
+use crate::{level_size, max_levels};
+
+/// The pixel encodings understood by the GameCube/Wii texture hardware.
+///
+/// The discriminants are the values stored in the encoding byte of both
+/// GCT (0x14) and TEX0 (0x23) headers.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum EncodingType {
+    I4 = 0x00,
+    I8 = 0x01,
+    Ia4 = 0x02,
+    Ia8 = 0x03,
+    Rgb565 = 0x04,
+    Rgb5A3 = 0x05,
+    Rgba32 = 0x06,
+    Ci4 = 0x08,
+    Ci8 = 0x09,
+    Ci14x2 = 0x0A,
+    Cmpr = 0x0E,
+}
+
+const ENCODING_NAMES: [(&str, EncodingType); 11] = [
+    ("i4", EncodingType::I4),
+    ("i8", EncodingType::I8),
+    ("ia4", EncodingType::Ia4),
+    ("ia8", EncodingType::Ia8),
+    ("rgb565", EncodingType::Rgb565),
+    ("rgb5a3", EncodingType::Rgb5A3),
+    ("rgba32", EncodingType::Rgba32),
+    ("ci4", EncodingType::Ci4),
+    ("ci8", EncodingType::Ci8),
+    ("ci14x2", EncodingType::Ci14x2),
+    ("cmpr", EncodingType::Cmpr),
+];
+
+impl EncodingType {
+    pub fn from_byte(byte: u8) -> Option<EncodingType> {
+        FromPrimitive::from_u8(byte)
+    }
+
+    /// Looks up an encoding by its usual name, such as `"rgb5a3"` or `"cmpr"`.
+    pub fn from_name(name: &str) -> Option<EncodingType> {
+        ENCODING_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, e)| *e)
+    }
+
+    pub fn name(self) -> &'static str {
+        match ENCODING_NAMES.iter().find(|(_, e)| *e == self) {
+            Some((n, _)) => n,
+            None => unreachable!(),
+        }
+    }
+
+    /// Whether image data in this encoding indexes into a separate palette.
+    pub fn has_palette(self) -> bool {
+        matches!(
+            self,
+            EncodingType::Ci4 | EncodingType::Ci8 | EncodingType::Ci14x2
+        )
+    }
+
+    /// Size in bytes of the palette that follows the image data in a GCT:
+    /// two bytes for each of the 16, 256 or 16384 colours an index can pick.
+    pub fn palette_size(self) -> usize {
+        match self {
+            EncodingType::Ci4 => 0x20,
+            EncodingType::Ci8 => 0x200,
+            EncodingType::Ci14x2 => 0x8000,
+            _ => 0,
+        }
+    }
+
+    /// Bits used to store each pixel.
+    pub fn bits_per_pixel(self) -> usize {
+        match self {
+            EncodingType::I4 | EncodingType::Ci4 | EncodingType::Cmpr => 4,
+            EncodingType::I8 | EncodingType::Ia4 | EncodingType::Ci8 => 8,
+            EncodingType::Ia8
+            | EncodingType::Rgb565
+            | EncodingType::Rgb5A3
+            | EncodingType::Ci14x2 => 16,
+            EncodingType::Rgba32 => 32,
+        }
+    }
+
+    /// Width in pixels of the tiles the image data is stored in.
+    pub fn block_width(self) -> usize {
+        match self.bits_per_pixel() {
+            4 | 8 => 8,
+            _ => 4,
+        }
+    }
+
+    /// Height in pixels of the tiles the image data is stored in.
+    pub fn block_height(self) -> usize {
+        match self.bits_per_pixel() {
+            4 => 8,
+            _ => 4,
+        }
+    }
+
+    /// Size in bytes of the image data for a `width` by `height` image, with
+    /// both dimensions padded up to whole tiles.
+    pub fn image_size(self, width: usize, height: usize) -> usize {
+        let padded_width = width.div_ceil(self.block_width()) * self.block_width();
+        let padded_height = height.div_ceil(self.block_height()) * self.block_height();
+        padded_width * padded_height * self.bits_per_pixel() / 8
+    }
+
+    /// Size in bytes of the image data of the first `levels` levels of the
+    /// mip chain of a `width` by `height` texture, each padded out to whole
+    /// blocks. Levels past the 1x1 one don't exist, so they add nothing.
+    pub fn chain_size(self, width: u16, height: u16, levels: u32) -> usize {
+        (0..levels.min(max_levels(width, height)))
+            .map(|level| {
+                let (width, height) = level_size(width, height, level);
+                self.image_size(width as usize, height as usize)
+            })
+            .sum()
+    }
+}
+
+/// The colour formats a palette can be stored in.
+///
+/// Stored at 0x15 in a GCT and at 0x18 (as an int) in a PLT0.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum PaletteFormat {
+    Ia8 = 0x00,
+    Rgb565 = 0x01,
+    Rgb5A3 = 0x02,
+}
+
+impl PaletteFormat {
+    pub fn from_byte(byte: u8) -> Option<PaletteFormat> {
+        FromPrimitive::from_u8(byte)
+    }
+
+    /// Looks up a palette format by its usual name, such as `"rgb565"`.
+    pub fn from_name(name: &str) -> Option<PaletteFormat> {
+        [
+            PaletteFormat::Ia8,
+            PaletteFormat::Rgb565,
+            PaletteFormat::Rgb5A3,
+        ]
+        .iter()
+        .find(|f| f.name().eq_ignore_ascii_case(name))
+        .copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PaletteFormat::Ia8 => "ia8",
+            PaletteFormat::Rgb565 => "rgb565",
+            PaletteFormat::Rgb5A3 => "rgb5a3",
+        }
+    }
+}
+
+impl Default for PaletteFormat {
+    /// The format a GCT is given when converted without a PLT0.
+    fn default() -> PaletteFormat {
+        PaletteFormat::Rgb5A3
+    }
+}
@@ -0,0 +1,236 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use crate::{EncodingType, FileKind};
+
+/// Everything that can go wrong while parsing or building a texture.
+#[derive(Debug)]
+pub enum GctconvError {
+    Io(io::Error),
+    PngEncoding(png::EncodingError),
+    PngDecoding(png::DecodingError),
+    /// The file ends before `needed` bytes could be read at `offset`.
+    Truncated {
+        offset: usize,
+        needed: usize,
+    },
+    /// A size or offset in the header can't be right, such as a TEX0 size
+    /// smaller than its header.
+    BadHeaderValue {
+        offset: usize,
+        value: usize,
+    },
+    /// The file doesn't start with the `expected` magic.
+    BadMagic {
+        expected: [u8; 4],
+    },
+    /// The file doesn't start with the magic of any format gctconv reads.
+    UnknownFormat,
+    /// The file is a `found`, where one of `expected` was needed.
+    WrongFormat {
+        found: FileKind,
+        expected: &'static [FileKind],
+    },
+    /// The file is a version of its format gctconv can't read or write.
+    UnsupportedVersion {
+        kind: FileKind,
+        version: u32,
+    },
+    UnsupportedEncoding(u8),
+    UnsupportedPaletteFormat(u8),
+    /// The encoding has no palette, so there is no PLT0 to build.
+    NoPalette,
+    /// The encoding needs a palette to decode, but none was given.
+    MissingPalette,
+    /// There is less image data than the dimensions and encoding need.
+    DataTooShort {
+        needed: usize,
+        found: usize,
+    },
+    /// Texture dimensions are stored as shorts.
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+    },
+    /// TEX0/PLT0 names are ascii only.
+    NonAsciiName(String),
+    /// TEX0/PLT0 names are at most 255 bytes long.
+    NameTooLong(String),
+    /// Two entries of a BRRES folder have the same name.
+    DuplicateName(String),
+    /// Only TEX0s and PLT0s can be written into a BRRES.
+    UnsupportedSubFile([u8; 4]),
+    /// A BRRES has no entry called `name` in `folder`.
+    MissingEntry {
+        folder: &'static str,
+        name: String,
+    },
+    /// A texture would replace one of a different size or encoding, which
+    /// the materials using it may not expect.
+    IncompatibleTexture {
+        name: String,
+        expected: (u16, u16, EncodingType),
+        found: (u16, u16, EncodingType),
+    },
+    /// A texture would replace one that differs in having a palette, which
+    /// would mean adding or removing a PLT0.
+    PaletteChange {
+        name: String,
+        from: EncodingType,
+        to: EncodingType,
+    },
+    /// A PNG was given to encode without saying which encoding to use.
+    MissingFormat,
+    /// Mipmaps were asked for on a texture that isn't a power of two wide
+    /// and high, which the hardware can't sample mipmapped.
+    NotPowerOfTwo {
+        width: u16,
+        height: u16,
+    },
+    /// Checking a texture turned up problems, each reported on its own.
+    Invalid(usize),
+    /// Two inputs of a batch would be converted to the same `output`.
+    SameOutput {
+        inputs: (PathBuf, PathBuf),
+        output: PathBuf,
+    },
+}
+
+impl fmt::Display for GctconvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GctconvError::Io(error) => write!(f, "{}", error),
+            GctconvError::PngEncoding(error) => write!(f, "{}", error),
+            GctconvError::PngDecoding(error) => write!(f, "{}", error),
+            GctconvError::Truncated { offset, needed } => write!(
+                f,
+                "file is truncated, needed {:#x} bytes at {:#x}",
+                needed, offset
+            ),
+            GctconvError::BadHeaderValue { offset, value } => write!(
+                f,
+                "header value {:#x} at {:#x} is out of range",
+                value, offset
+            ),
+            GctconvError::BadMagic { expected } => write!(
+                f,
+                "file doesn't start with \"{}\"",
+                String::from_utf8_lossy(expected)
+            ),
+            GctconvError::UnknownFormat => {
+                write!(f, "file isn't a GCT, TEX0, PLT0, BRRES or PNG")
+            }
+            GctconvError::WrongFormat { found, expected } => {
+                write!(f, "file is a {}, expected a ", found.name())?;
+                for (i, kind) in expected.iter().enumerate() {
+                    if i + 1 == expected.len() && i > 0 {
+                        write!(f, " or ")?;
+                    } else if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", kind.name())?;
+                }
+                Ok(())
+            }
+            GctconvError::UnsupportedVersion { kind, version } => {
+                write!(f, "unsupported {} version {}", kind.name(), version)
+            }
+            GctconvError::UnsupportedEncoding(byte) => {
+                write!(f, "unsupported encoding type {:#04x}", byte)
+            }
+            GctconvError::UnsupportedPaletteFormat(byte) => {
+                write!(f, "unsupported palette format {:#04x}", byte)
+            }
+            GctconvError::NoPalette => write!(f, "encoding has no palette"),
+            GctconvError::MissingPalette => write!(f, "encoding needs a palette"),
+            GctconvError::DataTooShort { needed, found } => {
+                write!(f, "image data is {:#x} bytes, needed {:#x}", found, needed)
+            }
+            GctconvError::ImageTooLarge { width, height } => {
+                write!(f, "{}x{} image is too large", width, height)
+            }
+            GctconvError::NonAsciiName(name) => write!(f, "\"{}\" isn't ascii", name),
+            GctconvError::NameTooLong(name) => write!(f, "\"{}\" is too long", name),
+            GctconvError::DuplicateName(name) => {
+                write!(f, "\"{}\" is in the same folder twice", name)
+            }
+            GctconvError::UnsupportedSubFile(magic) => write!(
+                f,
+                "can't write a {} into a BRRES, only TEX0s and PLT0s",
+                String::from_utf8_lossy(magic)
+            ),
+            GctconvError::MissingEntry { folder, name } => {
+                write!(f, "{} has nothing called \"{}\"", folder, name)
+            }
+            GctconvError::IncompatibleTexture {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "\"{}\" is a {}x{} {} texture, but the new one is {}x{} {}",
+                name,
+                expected.0,
+                expected.1,
+                expected.2.name(),
+                found.0,
+                found.1,
+                found.2.name()
+            ),
+            GctconvError::PaletteChange { name, from, to } => write!(
+                f,
+                "can't replace the {} texture \"{}\" with {}, as its palette would have to be {}",
+                from.name(),
+                name,
+                to.name(),
+                if to.has_palette() { "added" } else { "removed" }
+            ),
+            GctconvError::MissingFormat => {
+                write!(f, "PNG input needs an encoding, given with --format")
+            }
+            GctconvError::NotPowerOfTwo { width, height } => write!(
+                f,
+                "can't make mipmaps of a {}x{} texture, as it isn't a power of two wide and high; \
+                 fit it to one with --fit",
+                width, height
+            ),
+            GctconvError::Invalid(1) => write!(f, "found 1 problem"),
+            GctconvError::Invalid(count) => write!(f, "found {} problems", count),
+            GctconvError::SameOutput { inputs, output } => write!(
+                f,
+                "{} and {} would both be written to {}",
+                inputs.0.display(),
+                inputs.1.display(),
+                output.display()
+            ),
+        }
+    }
+}
+
+impl Error for GctconvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GctconvError::Io(error) => Some(error),
+            GctconvError::PngEncoding(error) => Some(error),
+            GctconvError::PngDecoding(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GctconvError {
+    fn from(error: io::Error) -> GctconvError {
+        GctconvError::Io(error)
+    }
+}
+
+impl From<png::EncodingError> for GctconvError {
+    fn from(error: png::EncodingError) -> GctconvError {
+        GctconvError::PngEncoding(error)
+    }
+}
+
+impl From<png::DecodingError> for GctconvError {
+    fn from(error: png::DecodingError) -> GctconvError {
+        GctconvError::PngDecoding(error)
+    }
+}
@@ -0,0 +1,272 @@
+//! Fitting images of any size to power of two dimensions the hardware is
+//! happy with.
+
+use crate::{resize, Filter, GctconvError, MAX_DIMENSION};
+
+/// How an image that isn't a power of two wide and high, or is larger than
+/// the hardware allows, is made into one that is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// Grows each side to the next power of two, repeating the edge pixels
+    /// so filtering at the border doesn't pick up a seam.
+    PadEdge,
+    /// Grows each side to the next power of two with transparent pixels.
+    PadTransparent,
+    /// Resamples each side to the nearest power of two.
+    Scale,
+    /// Cuts each side down to the power of two below it, keeping the
+    /// middle of the image.
+    Crop,
+}
+
+const FIT_NAMES: [(&str, Fit); 4] = [
+    ("pad", Fit::PadEdge),
+    ("pad-transparent", Fit::PadTransparent),
+    ("scale", Fit::Scale),
+    ("crop", Fit::Crop),
+];
+
+impl Fit {
+    pub fn from_name(name: &str) -> Option<Fit> {
+        FIT_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, f)| *f)
+    }
+
+    pub fn name(self) -> &'static str {
+        match FIT_NAMES.iter().find(|(_, f)| *f == self) {
+            Some((n, _)) => n,
+            None => unreachable!(),
+        }
+    }
+}
+
+fn previous_power_of_two(size: u16) -> u16 {
+    1 << (u16::BITS - 1 - size.leading_zeros())
+}
+
+fn nearest_power_of_two(size: u16) -> u16 {
+    let below = previous_power_of_two(size);
+    let above = size.next_power_of_two();
+    if size - below < above - size {
+        below
+    } else {
+        above
+    }
+}
+
+/// Fits a `width` by `height` RGBA8 image, four bytes per pixel, with
+/// `fit`, returning its new width, height and pixels. Sides are never
+/// larger than `MAX_DIMENSION`; padding an image that is scales it down
+/// first, keeping its aspect ratio. `filter` is used for any scaling.
+pub fn fit(
+    rgba: &[u8],
+    width: u16,
+    height: u16,
+    fit: Fit,
+    filter: Filter,
+) -> Result<(u16, u16, Vec<u8>), GctconvError> {
+    let needed = width as usize * height as usize * 4;
+    if rgba.len() < needed {
+        return Err(GctconvError::DataTooShort {
+            needed,
+            found: rgba.len(),
+        });
+    }
+    if width == 0 || height == 0 {
+        return Ok((width, height, rgba.to_vec()));
+    }
+
+    match fit {
+        Fit::Scale => {
+            let new_width = nearest_power_of_two(width.min(MAX_DIMENSION));
+            let new_height = nearest_power_of_two(height.min(MAX_DIMENSION));
+            let scaled = scale(rgba, width, height, new_width, new_height, filter)?;
+            Ok((new_width, new_height, scaled))
+        }
+        Fit::Crop => {
+            let new_width = previous_power_of_two(width).min(MAX_DIMENSION);
+            let new_height = previous_power_of_two(height).min(MAX_DIMENSION);
+            let left = ((width - new_width) / 2) as usize;
+            let top = ((height - new_height) / 2) as usize;
+
+            let row_size = new_width as usize * 4;
+            let mut cropped = Vec::with_capacity(row_size * new_height as usize);
+            for y in top..top + new_height as usize {
+                let start = (y * width as usize + left) * 4;
+                cropped.extend(&rgba[start..start + row_size]);
+            }
+            Ok((new_width, new_height, cropped))
+        }
+        Fit::PadEdge | Fit::PadTransparent => {
+            // shrink anything too large for the largest texture first
+            let largest = width.max(height);
+            let (width, height, rgba) = if largest > MAX_DIMENSION {
+                let shrink = |size: u16| {
+                    let size = size as u32 * MAX_DIMENSION as u32 / largest as u32;
+                    (size as u16).max(1)
+                };
+                let (new_width, new_height) = (shrink(width), shrink(height));
+                let scaled = scale(rgba, width, height, new_width, new_height, filter)?;
+                (new_width, new_height, scaled)
+            } else {
+                (width, height, rgba.to_vec())
+            };
+
+            let new_width = width.next_power_of_two();
+            let new_height = height.next_power_of_two();
+            let mut padded = vec![0; new_width as usize * new_height as usize * 4];
+            for y in 0..new_height as usize {
+                for x in 0..new_width as usize {
+                    let inside = x < width as usize && y < height as usize;
+                    if !inside && fit == Fit::PadTransparent {
+                        continue;
+                    }
+                    // past the edge, repeat the nearest pixel on it
+                    let from_x = x.min(width as usize - 1);
+                    let from_y = y.min(height as usize - 1);
+                    let from = (from_y * width as usize + from_x) * 4;
+                    let to = (y * new_width as usize + x) * 4;
+                    padded[to..to + 4].copy_from_slice(&rgba[from..from + 4]);
+                }
+            }
+            Ok((new_width, new_height, padded))
+        }
+    }
+}
+
+fn scale(
+    rgba: &[u8],
+    width: u16,
+    height: u16,
+    new_width: u16,
+    new_height: u16,
+    filter: Filter,
+) -> Result<Vec<u8>, GctconvError> {
+    if (new_width, new_height) == (width, height) {
+        return Ok(rgba.to_vec());
+    }
+    resize(
+        rgba,
+        width as usize,
+        height as usize,
+        new_width as usize,
+        new_height as usize,
+        filter,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image where every pixel is its own x and y, opaque.
+    fn image(width: u16, height: u16) -> Vec<u8> {
+        (0..height as usize * width as usize)
+            .flat_map(|i| {
+                [
+                    (i % width as usize) as u8,
+                    (i / width as usize) as u8,
+                    0,
+                    255,
+                ]
+            })
+            .collect()
+    }
+
+    fn pixel(rgba: &[u8], width: u16, x: usize, y: usize) -> [u8; 4] {
+        let at = (y * width as usize + x) * 4;
+        [rgba[at], rgba[at + 1], rgba[at + 2], rgba[at + 3]]
+    }
+
+    fn sizes(width: u16, height: u16) -> Vec<(u16, u16)> {
+        let rgba = image(width, height);
+        [Fit::PadEdge, Fit::PadTransparent, Fit::Scale, Fit::Crop]
+            .iter()
+            .map(|&f| {
+                let (w, h, fitted) = fit(&rgba, width, height, f, Filter::Box).unwrap();
+                assert_eq!(fitted.len(), w as usize * h as usize * 4);
+                (w, h)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pad_repeats_the_edge() {
+        let (width, height, rgba) = fit(&image(5, 3), 5, 3, Fit::PadEdge, Filter::Box).unwrap();
+        assert_eq!((width, height), (8, 4));
+        assert_eq!(pixel(&rgba, width, 2, 1), [2, 1, 0, 255]);
+        assert_eq!(pixel(&rgba, width, 7, 1), [4, 1, 0, 255]);
+        assert_eq!(pixel(&rgba, width, 7, 3), [4, 2, 0, 255]);
+    }
+
+    #[test]
+    fn pad_transparent_leaves_the_rest_clear() {
+        let (width, height, rgba) =
+            fit(&image(5, 3), 5, 3, Fit::PadTransparent, Filter::Box).unwrap();
+        assert_eq!((width, height), (8, 4));
+        assert_eq!(pixel(&rgba, width, 4, 2), [4, 2, 0, 255]);
+        assert_eq!(pixel(&rgba, width, 5, 0), [0; 4]);
+        assert_eq!(pixel(&rgba, width, 0, 3), [0; 4]);
+    }
+
+    #[test]
+    fn scale_goes_to_the_nearest_power_of_two() {
+        let (width, height, rgba) = fit(
+            &[9, 9, 9, 255].repeat(5 * 12),
+            5,
+            12,
+            Fit::Scale,
+            Filter::Box,
+        )
+        .unwrap();
+        assert_eq!((width, height), (4, 16));
+        assert!(rgba.chunks_exact(4).all(|p| p == [9, 9, 9, 255]));
+    }
+
+    #[test]
+    fn crop_keeps_the_middle() {
+        let (width, height, rgba) = fit(&image(7, 5), 7, 5, Fit::Crop, Filter::Box).unwrap();
+        assert_eq!((width, height), (4, 4));
+        assert_eq!(pixel(&rgba, width, 0, 0), [1, 0, 0, 255]);
+        assert_eq!(pixel(&rgba, width, 3, 3), [4, 3, 0, 255]);
+    }
+
+    #[test]
+    fn images_smaller_than_a_tile() {
+        // fitted sizes can still be smaller than an encoding's blocks,
+        // which the encoder pads out as it would any other size
+        assert_eq!(sizes(3, 3), [(4, 4), (4, 4), (4, 4), (2, 2)]);
+        assert_eq!(sizes(1, 6), [(1, 8), (1, 8), (1, 8), (1, 4)]);
+    }
+
+    #[test]
+    fn images_over_the_largest_size() {
+        assert_eq!(
+            sizes(1500, 10),
+            [(1024, 8), (1024, 8), (1024, 8), (1024, 8)]
+        );
+        assert_eq!(sizes(2000, 3), [(1024, 1), (1024, 1), (1024, 4), (1024, 2)]);
+    }
+
+    #[test]
+    fn powers_of_two_are_kept() {
+        let rgba = image(16, 8);
+        for f in [Fit::PadEdge, Fit::PadTransparent, Fit::Scale, Fit::Crop] {
+            assert_eq!(
+                fit(&rgba, 16, 8, f, Filter::Lanczos).unwrap(),
+                (16, 8, rgba.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn short_images_are_an_error() {
+        let fitted = fit(&[0; 20], 3, 2, Fit::Scale, Filter::Box);
+        assert!(matches!(
+            fitted,
+            Err(GctconvError::DataTooShort { needed: 24, .. })
+        ));
+    }
+}
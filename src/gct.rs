@@ -0,0 +1,420 @@
+use std::convert::TryFrom;
+
+use crate::{
+    check_magic, decode, decode::palette_colour, encode, encode::encode_indexed, fit, level_size,
+    max_levels, mipmap::levels_in_size, read_bytes, read_u16, read_u8, resize,
+    validate::check_image, EncodeOptions, EncodingType, Filter, GctconvError, PaletteFormat, Plt0,
+    Problem, Tex0, HEADER_SIZE,
+};
+
+const GCT_HEADER_START: [u8; 12] = [
+    0x47, 0x43, 0x4E, 0x54, 0x00, 0x00, 0x00, 0x03, 0x00, 0x40, 0x00, 0x00,
+];
+
+const TREY_PAD: [u8; 0x20] = [
+    0x20, 0x74, 0x72, 0x65, 0x79, 0x61, 0x72, 0x63, 0x68, 0x67, 0x63, 0x74, 0x2E, 0x38, 0x62, 0x69,
+    0x20, 0x76, 0x32, 0x2E, 0x32, 0x2E, 0x34, 0x23, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// A Treyarch GameCube texture (`GCNT`).
+///
+/// The image data follows the 0x40 byte header, and for palette encodings
+/// the palette follows the image data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gct {
+    pub width: u16,
+    pub height: u16,
+    pub encoding: EncodingType,
+    /// Only meaningful when `encoding` has a palette.
+    pub palette_format: PaletteFormat,
+    /// The image data of every mip level, one after the other.
+    pub image_data: Vec<u8>,
+    pub palette_data: Vec<u8>,
+    /// The number of images in the mip chain, 1 for just the texture.
+    pub levels: u32,
+}
+
+impl Gct {
+    pub fn parse(bytes: &[u8]) -> Result<Gct, GctconvError> {
+        check_magic(bytes, b"GCNT")?;
+        read_bytes(bytes, 0, HEADER_SIZE)?;
+
+        let width = read_u16(bytes, 0x10)?;
+        let height = read_u16(bytes, 0x12)?;
+        let enc_byte = read_u8(bytes, 0x14)?;
+        let encoding = match EncodingType::from_byte(enc_byte) {
+            Some(e) => e,
+            None => return Err(GctconvError::UnsupportedEncoding(enc_byte)),
+        };
+        // whatever byte a GCT without a palette has, it reads as the format
+        // one converted from a TEX0 gets, so the two compare equal
+        let rgb_byte = read_u8(bytes, 0x15)?;
+        let palette_format = match PaletteFormat::from_byte(rgb_byte) {
+            _ if !encoding.has_palette() => PaletteFormat::default(),
+            Some(f) => f,
+            None => return Err(GctconvError::UnsupportedPaletteFormat(rgb_byte)),
+        };
+
+        // the palette takes up the end of the file
+        let data = &bytes[HEADER_SIZE..];
+        let image_size = match data.len().checked_sub(encoding.palette_size()) {
+            Some(s) => s,
+            None => {
+                return Err(GctconvError::Truncated {
+                    offset: HEADER_SIZE,
+                    needed: encoding.palette_size(),
+                })
+            }
+        };
+        let (image_data, palette_data) = data.split_at(image_size);
+
+        let levels = levels_in_size(encoding, width, height, image_data.len());
+
+        Ok(Gct {
+            width,
+            height,
+            encoding,
+            palette_format,
+            image_data: image_data.to_vec(),
+            palette_data: palette_data.to_vec(),
+            levels,
+        })
+    }
+
+    /// Encodes a `width` by `height` RGBA8 image, four bytes per pixel,
+    /// building a palette for colour index encodings. With `options.fit`
+    /// the image is fitted to a power of two size first, so the texture may
+    /// not be `width` by `height`.
+    pub fn from_rgba(
+        encoding: EncodingType,
+        width: u16,
+        height: u16,
+        rgba: &[u8],
+        options: &EncodeOptions,
+    ) -> Result<Gct, GctconvError> {
+        let fitted;
+        let (width, height, rgba) = match options.fit {
+            Some(f) => {
+                fitted = fit(rgba, width, height, f, options.fit_filter)?;
+                (fitted.0, fitted.1, &fitted.2[..])
+            }
+            None => (width, height, rgba),
+        };
+        let (image_data, palette_data) = encode(encoding, width, height, rgba, options)?;
+        Ok(Gct {
+            width,
+            height,
+            encoding,
+            palette_format: if encoding.has_palette() {
+                options.palette_format
+            } else {
+                PaletteFormat::default()
+            },
+            image_data,
+            palette_data,
+            levels: 1,
+        })
+    }
+
+    /// Replaces any mip levels with ones resampled from the base level with
+    /// `filter`, for `levels` images in all, or as many as there can be.
+    ///
+    /// The base level is kept as it is, and the levels of colour index
+    /// encodings use its palette. Only textures that are a power of two wide
+    /// and high can have more than one level.
+    pub fn generate_mipmaps(
+        &mut self,
+        levels: u32,
+        filter: Filter,
+        options: &EncodeOptions,
+    ) -> Result<(), GctconvError> {
+        if self.encoding.has_palette() && self.palette_data.is_empty() {
+            return Err(GctconvError::MissingPalette);
+        }
+        let levels = levels.clamp(1, max_levels(self.width, self.height));
+        if levels > 1 && !(self.width.is_power_of_two() && self.height.is_power_of_two()) {
+            return Err(GctconvError::NotPowerOfTwo {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        let rgba = self.to_rgba()?;
+        let palette: Vec<[u8; 4]> = self
+            .palette_data
+            .chunks_exact(2)
+            .map(|entry| {
+                palette_colour(
+                    self.palette_format,
+                    u16::from_be_bytes([entry[0], entry[1]]),
+                )
+            })
+            .collect();
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        self.image_data
+            .truncate(self.encoding.image_size(width, height));
+        for level in 1..levels {
+            let (level_width, level_height) = level_size(self.width, self.height, level);
+            let resized = resize(
+                &rgba,
+                width,
+                height,
+                level_width as usize,
+                level_height as usize,
+                filter,
+            )?;
+            let data = if self.encoding.has_palette() {
+                encode_indexed(self.encoding, level_width, level_height, &resized, &palette)
+            } else {
+                encode(self.encoding, level_width, level_height, &resized, options)?.0
+            };
+            self.image_data.extend(data);
+        }
+        self.levels = levels;
+        Ok(())
+    }
+
+    /// Decodes the base level to RGBA8, four bytes per pixel.
+    pub fn to_rgba(&self) -> Result<Vec<u8>, GctconvError> {
+        let palette = if self.encoding.has_palette() {
+            Some((self.palette_format, self.palette_data.as_slice()))
+        } else {
+            None
+        };
+        decode(
+            self.encoding,
+            self.width,
+            self.height,
+            &self.image_data,
+            palette,
+        )
+    }
+
+    /// Everything about the texture that its encoding or the hardware
+    /// won't accept.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = check_image(
+            self.encoding,
+            self.width,
+            self.height,
+            self.levels,
+            self.image_data.len(),
+        );
+        let expected = self.encoding.palette_size();
+        if self.palette_data.len() != expected {
+            problems.push(Problem::PaletteSize {
+                encoding: self.encoding,
+                expected,
+                found: self.palette_data.len(),
+            });
+        }
+        problems
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = GCT_HEADER_START.to_vec();
+        header.extend(&(self.image_data.len() as u32).to_be_bytes());
+        header.extend(&self.width.to_be_bytes());
+        header.extend(&self.height.to_be_bytes());
+        header.push(self.encoding as u8);
+        header.push(if self.encoding.has_palette() {
+            self.palette_format as u8
+        } else {
+            0x01
+        });
+        header.extend(&[0; 10]);
+        header.extend(&TREY_PAD);
+
+        let mut gct_file = header;
+        gct_file.extend(&self.image_data);
+        gct_file.extend(&self.palette_data);
+        gct_file
+    }
+}
+
+impl From<&Tex0> for Gct {
+    /// Builds a GCT with no palette data; see `From<(&Tex0, &Plt0)>` for
+    /// palette encodings.
+    fn from(tex0: &Tex0) -> Gct {
+        Gct {
+            width: tex0.width,
+            height: tex0.height,
+            encoding: tex0.encoding,
+            palette_format: PaletteFormat::default(),
+            image_data: tex0.data.clone(),
+            palette_data: Vec::new(),
+            levels: tex0.levels,
+        }
+    }
+}
+
+impl From<(&Tex0, &Plt0)> for Gct {
+    fn from((tex0, plt0): (&Tex0, &Plt0)) -> Gct {
+        Gct {
+            palette_format: plt0.format,
+            palette_data: plt0.data.clone(),
+            ..Gct::from(tex0)
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Gct {
+    type Error = GctconvError;
+
+    fn try_from(bytes: &[u8]) -> Result<Gct, GctconvError> {
+        Gct::parse(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOUR: [u8; 4] = [0x84, 0x82, 0x84, 0xFF];
+
+    /// The image of every level of a texture, decoded.
+    fn levels(gct: &Gct) -> Vec<Vec<u8>> {
+        let palette = Some((gct.palette_format, gct.palette_data.as_slice()));
+        let palette = palette.filter(|_| gct.encoding.has_palette());
+        let mut start = 0;
+        (0..gct.levels)
+            .map(|level| {
+                let (width, height) = level_size(gct.width, gct.height, level);
+                let data = &gct.image_data[start..];
+                start += gct.encoding.image_size(width as usize, height as usize);
+                decode(gct.encoding, width, height, data, palette).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mip_chain() {
+        let options = EncodeOptions::default();
+        let rgba = COLOUR.repeat(16 * 8);
+        let mut gct = Gct::from_rgba(EncodingType::Rgb565, 16, 8, &rgba, &options).unwrap();
+        gct.generate_mipmaps(u32::MAX, Filter::Box, &options)
+            .unwrap();
+
+        assert_eq!(gct.levels, 5);
+        let sizes: Vec<(u16, u16)> = (0..5).map(|l| level_size(16, 8, l)).collect();
+        assert_eq!(sizes, [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]);
+        // 4x4 blocks of 2 byte texels, the small levels padded to one block
+        assert_eq!(gct.image_data.len(), 0x100 + 0x40 + 0x20 * 3);
+        for (level, (width, height)) in levels(&gct).iter().zip(sizes) {
+            assert_eq!(*level, COLOUR.repeat(width as usize * height as usize));
+        }
+
+        let tex0 = Tex0::from(&gct).to_bytes().unwrap();
+        let field = |offset: usize| {
+            [
+                tex0[offset],
+                tex0[offset + 1],
+                tex0[offset + 2],
+                tex0[offset + 3],
+            ]
+        };
+        assert_eq!(u32::from_be_bytes(field(0x24)), 5);
+        assert_eq!(f32::from_be_bytes(field(0x28)), 0.0);
+        assert_eq!(f32::from_be_bytes(field(0x2C)), 4.0);
+    }
+
+    #[test]
+    fn colour_index_levels_share_the_palette() {
+        let options = EncodeOptions::default();
+        let rgba: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| {
+                if i % 8 < 4 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                }
+            })
+            .collect();
+        let mut gct = Gct::from_rgba(EncodingType::Ci8, 8, 8, &rgba, &options).unwrap();
+        let palette = gct.palette_data.clone();
+        gct.generate_mipmaps(u32::MAX, Filter::Box, &options)
+            .unwrap();
+
+        assert_eq!(gct.levels, 4);
+        assert_eq!(gct.palette_data, palette);
+        // the 1x1 level averages to purple, which has to be one of the two
+        for level in levels(&gct) {
+            for pixel in level.chunks_exact(4) {
+                assert!(
+                    pixel == [255, 0, 0, 255] || pixel == [0, 0, 255, 255],
+                    "{:?}",
+                    pixel
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mip_chain_round_trips() {
+        let options = EncodeOptions::default();
+        let rgba: Vec<u8> = (0..16 * 16).flat_map(|i| [i as u8, 0, 0, 255]).collect();
+        let mut gct = Gct::from_rgba(EncodingType::Ci8, 16, 16, &rgba, &options).unwrap();
+        gct.generate_mipmaps(3, Filter::Box, &options).unwrap();
+
+        let parsed = Gct::parse(&gct.to_bytes()).unwrap();
+        assert_eq!(parsed.levels, 3);
+        assert_eq!(parsed, gct);
+
+        // and across to a TEX0 and back
+        let tex0 = Tex0::parse(&Tex0::from(&gct).to_bytes().unwrap()).unwrap();
+        let plt0 = Plt0::try_from(&gct).unwrap();
+        assert_eq!(tex0.levels, 3);
+        assert_eq!(Gct::from((&tex0, &plt0)), gct);
+    }
+
+    #[test]
+    fn only_power_of_two_data_is_a_mip_chain() {
+        // 12x8 and then 6x4 RGB565, which is the size of a chain but can't
+        // be one
+        let gct = Gct {
+            width: 12,
+            height: 8,
+            encoding: EncodingType::Rgb565,
+            palette_format: PaletteFormat::default(),
+            image_data: vec![0; 0xC0 + 0x40],
+            palette_data: Vec::new(),
+            levels: 1,
+        };
+        assert_eq!(Gct::parse(&gct.to_bytes()).unwrap().levels, 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let options = EncodeOptions {
+            palette_format: PaletteFormat::Ia8,
+            ..EncodeOptions::default()
+        };
+        let rgba: Vec<u8> = (0..8 * 4)
+            .flat_map(|i| [i as u8 * 8, 0x40, 0, 0xFF])
+            .collect();
+        for encoding in [EncodingType::Rgb5A3, EncodingType::Ci4, EncodingType::Cmpr] {
+            let gct = Gct::from_rgba(encoding, 8, 4, &rgba, &options).unwrap();
+            assert_eq!(Gct::parse(&gct.to_bytes()).unwrap(), gct, "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn palette_format_without_a_palette() {
+        let tex0 = Tex0 {
+            name: String::new(),
+            width: 8,
+            height: 4,
+            encoding: EncodingType::I8,
+            data: vec![0x80; 0x20],
+            levels: 1,
+            version: 1,
+        };
+        let mut bytes = Gct::from(&tex0).to_bytes();
+        for byte in [0x00, 0x01, 0x02, 0xFF] {
+            bytes[0x15] = byte;
+            let gct = Gct::parse(&bytes).unwrap();
+            assert_eq!(gct.palette_format, PaletteFormat::Rgb5A3);
+            assert_eq!(gct, Gct::from(&tex0));
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Reading and writing decoded RGBA8 images as PNG.
+
+use std::{convert::TryFrom, io::Cursor};
+
+use crate::GctconvError;
+
+/// Encodes a `width` by `height` RGBA8 buffer as a PNG.
+pub fn rgba_to_png(width: u16, height: u16, rgba: &[u8]) -> Result<Vec<u8>, GctconvError> {
+    let mut png_file = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut png_file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+
+    Ok(png_file)
+}
+
+/// Decodes a PNG of any colour type and bit depth to RGBA8, returning its
+/// width, height and pixels.
+pub fn png_to_rgba(png_file: &[u8]) -> Result<(u16, u16, Vec<u8>), GctconvError> {
+    let mut decoder = png::Decoder::new(Cursor::new(png_file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let (width, height) = (reader.info().width, reader.info().height);
+    let (width16, height16) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => return Err(GctconvError::ImageTooLarge { width, height }),
+    };
+
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or(0)];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        // palettes are always expanded by the transformations above
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            buffer.iter().flat_map(|&i| [i, i, i, 0xFF]).collect()
+        }
+    };
+
+    Ok((width16, height16, rgba))
+}
@@ -0,0 +1,331 @@
+//! Field by field descriptions of GCT, TEX0 and PLT0 headers, for looking
+//! into files without a hex editor.
+
+use std::fmt;
+
+use crate::{
+    max_levels, mipmap::levels_in_size, read_bytes, read_name, read_u16, read_u32, read_u8, tex0,
+    EncodingType, FileKind, GctconvError, PaletteFormat, Tex0, HEADER_SIZE,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    /// Counts, dimensions and enums.
+    Int(i64),
+    /// Offsets and sizes, which are easier to read in hex.
+    Offset(i64),
+    Float(f32),
+    Text(String),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::Offset(v) if *v < 0 => write!(f, "-{:#x}", -v),
+            FieldValue::Offset(v) => write!(f, "{:#x}", v),
+            FieldValue::Float(v) => write!(f, "{:?}", v),
+            FieldValue::Text(v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+/// A single field of a header, as stored in the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderField {
+    pub offset: usize,
+    pub name: &'static str,
+    pub value: FieldValue,
+    /// What an enum value stands for, such as `"cmpr"` for encoding 0x0E.
+    pub meaning: Option<&'static str>,
+}
+
+/// Every header field of a file, along with the values derived from them.
+///
+/// Unlike the parsers, this reads whatever is there: unknown encodings and
+/// sizes that disagree with the data are reported rather than rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileInfo {
+    pub kind: FileKind,
+    pub fields: Vec<HeaderField>,
+    /// The name from the name block trailing a TEX0 or PLT0.
+    pub name: Option<String>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub encoding: Option<EncodingType>,
+    pub palette_format: Option<PaletteFormat>,
+    pub palette_entries: Option<usize>,
+    /// Number of images in the mipmap chain, 1 when there are no mipmaps.
+    pub levels: Option<u32>,
+    /// Size of the image data, or the palette data of a PLT0, going by the
+    /// header.
+    pub data_size: usize,
+    /// Size of the data the encoding and dimensions, or the entry count of
+    /// a PLT0, call for.
+    pub expected_data_size: Option<usize>,
+    /// Size of the file going by the header. For a TEX0 or PLT0 this ends
+    /// where the name block starts.
+    pub declared_size: usize,
+    pub file_size: usize,
+}
+
+impl FileInfo {
+    pub fn read(bytes: &[u8]) -> Result<FileInfo, GctconvError> {
+        match FileKind::detect(bytes) {
+            Some(FileKind::Gct) => read_gct(bytes),
+            Some(FileKind::Tex0) => read_tex0(bytes),
+            Some(FileKind::Plt0) => read_plt0(bytes),
+            Some(found) => Err(GctconvError::WrongFormat {
+                found,
+                expected: &[FileKind::Gct, FileKind::Tex0, FileKind::Plt0],
+            }),
+            None => Err(GctconvError::UnknownFormat),
+        }
+    }
+
+    /// Describes every way the header disagrees with itself or the file.
+    pub fn mismatches(&self) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if let Some(expected) = self.expected_data_size {
+            if expected != self.data_size {
+                mismatches.push(format!(
+                    "header gives {:#x} bytes of data, but {:#x} are needed",
+                    self.data_size, expected
+                ));
+            }
+        }
+
+        if let (Some(width), Some(height), Some(levels)) = (self.width, self.height, self.levels) {
+            if levels > max_levels(width, height) {
+                mismatches.push(format!(
+                    "header gives {} images, but a {}x{} texture has at most {}",
+                    levels,
+                    width,
+                    height,
+                    max_levels(width, height)
+                ));
+            }
+        }
+
+        let too_short = self.file_size < self.declared_size;
+        let too_long = self.kind == FileKind::Gct && self.file_size > self.declared_size;
+        if too_short || too_long {
+            mismatches.push(format!(
+                "header gives a size of {:#x} bytes, but the file is {:#x}",
+                self.declared_size, self.file_size
+            ));
+        }
+        if self.kind != FileKind::Gct && self.name.is_none() {
+            mismatches.push("name offset doesn't point at a whole name".to_owned());
+        }
+        mismatches
+    }
+}
+
+/// Builds the list of fields, keeping track of the offset of each.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    fields: Vec<HeaderField>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(bytes: &'a [u8]) -> Fields<'a> {
+        Fields {
+            bytes,
+            fields: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, offset: usize, name: &'static str, value: FieldValue) {
+        self.fields.push(HeaderField {
+            offset,
+            name,
+            value,
+            meaning: None,
+        });
+    }
+
+    fn text(&mut self, offset: usize, len: usize, name: &'static str) -> Result<(), GctconvError> {
+        let raw = read_bytes(self.bytes, offset, len)?;
+        let end = raw.iter().position(|&b| b == 0).unwrap_or(len);
+        let text = String::from_utf8_lossy(&raw[..end]).into_owned();
+        self.push(offset, name, FieldValue::Text(text));
+        Ok(())
+    }
+
+    fn int(&mut self, offset: usize, name: &'static str, value: u32) -> u32 {
+        self.push(offset, name, FieldValue::Int(value as i64));
+        value
+    }
+
+    fn u8(&mut self, offset: usize, name: &'static str) -> Result<u8, GctconvError> {
+        let value = read_u8(self.bytes, offset)?;
+        Ok(self.int(offset, name, value as u32) as u8)
+    }
+
+    fn u16(&mut self, offset: usize, name: &'static str) -> Result<u16, GctconvError> {
+        let value = read_u16(self.bytes, offset)?;
+        Ok(self.int(offset, name, value as u32) as u16)
+    }
+
+    fn u32(&mut self, offset: usize, name: &'static str) -> Result<u32, GctconvError> {
+        let value = read_u32(self.bytes, offset)?;
+        Ok(self.int(offset, name, value))
+    }
+
+    fn offset(&mut self, offset: usize, name: &'static str) -> Result<usize, GctconvError> {
+        let value = read_u32(self.bytes, offset)?;
+        self.push(offset, name, FieldValue::Offset(value as i64));
+        Ok(value as usize)
+    }
+
+    /// Offsets back to the parent BRRES are negative.
+    fn signed_offset(&mut self, offset: usize, name: &'static str) -> Result<(), GctconvError> {
+        let value = read_u32(self.bytes, offset)? as i32;
+        self.push(offset, name, FieldValue::Offset(value as i64));
+        Ok(())
+    }
+
+    fn float(&mut self, offset: usize, name: &'static str) -> Result<(), GctconvError> {
+        let value = f32::from_bits(read_u32(self.bytes, offset)?);
+        self.push(offset, name, FieldValue::Float(value));
+        Ok(())
+    }
+
+    fn encoding(&mut self, value: u8) -> Option<EncodingType> {
+        let encoding = EncodingType::from_byte(value);
+        self.mean(encoding.map(|e| e.name()));
+        encoding
+    }
+
+    fn palette_format(&mut self, value: u8) -> Option<PaletteFormat> {
+        let format = PaletteFormat::from_byte(value);
+        self.mean(format.map(|f| f.name()));
+        format
+    }
+
+    /// Sets the meaning of the last field pushed.
+    fn mean(&mut self, meaning: Option<&'static str>) {
+        if let Some(field) = self.fields.last_mut() {
+            field.meaning = meaning;
+        }
+    }
+}
+
+fn read_gct(bytes: &[u8]) -> Result<FileInfo, GctconvError> {
+    let mut fields = Fields::new(bytes);
+    fields.text(0x00, 4, "magic")?;
+    fields.u32(0x04, "version")?;
+    let header_size = read_u16(bytes, 0x08)?;
+    fields.push(0x08, "header size", FieldValue::Offset(header_size as i64));
+    let data_size = fields.offset(0x0C, "image data size")?;
+    let width = fields.u16(0x10, "width")?;
+    let height = fields.u16(0x12, "height")?;
+    let enc_byte = fields.u8(0x14, "encoding")?;
+    let encoding = fields.encoding(enc_byte);
+    let rgb_byte = fields.u8(0x15, "palette format")?;
+    let palette_format = match encoding {
+        Some(e) if e.has_palette() => fields.palette_format(rgb_byte),
+        _ => None,
+    };
+    fields.text(0x20, 0x20, "signature")?;
+
+    let palette_size = encoding.map_or(0, |e| e.palette_size());
+    let levels = encoding.map_or(1, |e| levels_in_size(e, width, height, data_size));
+    Ok(FileInfo {
+        kind: FileKind::Gct,
+        fields: fields.fields,
+        name: None,
+        width: Some(width),
+        height: Some(height),
+        encoding,
+        palette_format,
+        palette_entries: palette_format.map(|_| palette_size / 2),
+        levels: Some(levels),
+        data_size,
+        expected_data_size: encoding.map(|e| e.chain_size(width, height, levels)),
+        declared_size: HEADER_SIZE
+            .saturating_add(data_size)
+            .saturating_add(palette_size),
+        file_size: bytes.len(),
+    })
+}
+
+/// The fields TEX0s and PLT0s share up to the section offsets, returning the
+/// size, version and data offset.
+fn read_common(fields: &mut Fields) -> Result<(usize, u32, usize), GctconvError> {
+    fields.text(0x00, 4, "magic")?;
+    let size = fields.offset(0x04, "size")?;
+    let version = fields.u32(0x08, "version")?;
+    fields.signed_offset(0x0C, "BRRES offset")?;
+    let data_offset = fields.offset(0x10, "data offset")?;
+    Ok((size, version, data_offset))
+}
+
+fn read_tex0(bytes: &[u8]) -> Result<FileInfo, GctconvError> {
+    let mut fields = Fields::new(bytes);
+    let (size, version, data_offset) = read_common(&mut fields)?;
+    if !Tex0::VERSIONS.contains(&version) {
+        fields.mean(Some("unsupported, read as version 1"));
+    }
+    if version == 2 {
+        fields.offset(0x14, "user data offset")?;
+    }
+    let name_field = tex0::name_offset_field(version);
+    fields.offset(name_field, "name offset")?;
+
+    // the rest are 4 bytes later in version 2
+    let at = name_field + 4;
+    fields.u32(at, "has palette")?;
+    let width = fields.u16(at + 0x04, "width")?;
+    let height = fields.u16(at + 0x06, "height")?;
+    let enc_byte = read_u8(bytes, at + 0x0B)?;
+    fields.u32(at + 0x08, "encoding")?;
+    let encoding = fields.encoding(enc_byte);
+    // some tools write 0 for a texture without mipmaps
+    let levels = fields.u32(at + 0x0C, "image count")?.max(1);
+    fields.float(at + 0x10, "min LOD")?;
+    fields.float(at + 0x14, "max LOD")?;
+
+    Ok(FileInfo {
+        kind: FileKind::Tex0,
+        fields: fields.fields,
+        name: read_name(bytes, 0, name_field).ok(),
+        width: Some(width),
+        height: Some(height),
+        encoding,
+        palette_format: None,
+        palette_entries: None,
+        levels: Some(levels),
+        data_size: size.saturating_sub(data_offset),
+        expected_data_size: encoding.map(|e| e.chain_size(width, height, levels)),
+        declared_size: size,
+        file_size: bytes.len(),
+    })
+}
+
+fn read_plt0(bytes: &[u8]) -> Result<FileInfo, GctconvError> {
+    let mut fields = Fields::new(bytes);
+    let (size, _, data_offset) = read_common(&mut fields)?;
+    fields.offset(0x14, "name offset")?;
+    let rgb_byte = read_u8(bytes, 0x1B)?;
+    fields.u32(0x18, "palette format")?;
+    let palette_format = fields.palette_format(rgb_byte);
+    let entries = fields.u16(0x1C, "entry count")? as usize;
+
+    Ok(FileInfo {
+        kind: FileKind::Plt0,
+        fields: fields.fields,
+        name: read_name(bytes, 0, 0x14).ok(),
+        width: None,
+        height: None,
+        encoding: None,
+        palette_format,
+        palette_entries: Some(entries),
+        levels: None,
+        data_size: size.saturating_sub(data_offset),
+        expected_data_size: Some(entries * 2),
+        declared_size: size,
+        file_size: bytes.len(),
+    })
+}
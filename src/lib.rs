@@ -0,0 +1,297 @@
+//! Conversion between Treyarch's GameCube `.gct` textures and the NW4R
+//! `.tex0`/`.plt0` sub-files used by Wii games and BrawlBox.
+
+use std::convert::TryFrom;
+
+#[macro_use]
+extern crate num_derive;
+
+mod brres;
+mod cmpr;
+mod decode;
+mod dither;
+mod encode;
+mod encoding;
+mod error;
+mod fit;
+mod gct;
+mod image;
+mod info;
+mod mipmap;
+mod palette;
+mod plt0;
+mod resample;
+mod tex0;
+mod validate;
+
+pub use brres::{Brres, Entry, Folder, SubFile, PALETTES, TEXTURES};
+pub use cmpr::CmprQuality;
+pub use decode::decode;
+pub use dither::Dither;
+pub use encode::{encode, EncodeOptions};
+pub use encoding::{EncodingType, PaletteFormat};
+pub use error::GctconvError;
+pub use fit::{fit, Fit};
+pub use gct::Gct;
+pub use image::{png_to_rgba, rgba_to_png};
+pub use info::{FieldValue, FileInfo, HeaderField};
+pub use mipmap::{level_size, max_levels};
+pub use plt0::Plt0;
+pub use resample::{resize, Filter};
+pub use tex0::Tex0;
+pub use validate::{Problem, MAX_DIMENSION};
+
+/// The kinds of file gctconv reads, told apart by their magic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Gct,
+    Tex0,
+    Plt0,
+    Brres,
+    Png,
+}
+
+const MAGICS: [(&[u8], FileKind); 5] = [
+    (b"GCNT", FileKind::Gct),
+    (b"TEX0", FileKind::Tex0),
+    (b"PLT0", FileKind::Plt0),
+    (b"bres", FileKind::Brres),
+    (b"\x89PNG\r\n\x1A\n", FileKind::Png),
+];
+
+impl FileKind {
+    /// Longest magic, and so the most bytes `detect` needs.
+    pub const MAGIC_SIZE: usize = 8;
+
+    pub fn detect(bytes: &[u8]) -> Option<FileKind> {
+        MAGICS
+            .iter()
+            .find(|(magic, _)| bytes.starts_with(magic))
+            .map(|(_, kind)| *kind)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::Gct => "GCT",
+            FileKind::Tex0 => "TEX0",
+            FileKind::Plt0 => "PLT0",
+            FileKind::Brres => "BRRES",
+            FileKind::Png => "PNG",
+        }
+    }
+}
+
+/// Size of the fixed headers at the start of GCT, TEX0 and PLT0 files.
+pub(crate) const HEADER_SIZE: usize = 0x40;
+
+pub(crate) fn read_bytes(
+    bytes: &[u8],
+    offset: usize,
+    needed: usize,
+) -> Result<&[u8], GctconvError> {
+    let end = offset.checked_add(needed);
+    match end.and_then(|end| bytes.get(offset..end)) {
+        Some(b) => Ok(b),
+        None => Err(GctconvError::Truncated { offset, needed }),
+    }
+}
+
+pub(crate) fn read_u8(bytes: &[u8], offset: usize) -> Result<u8, GctconvError> {
+    Ok(read_bytes(bytes, offset, 1)?[0])
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, GctconvError> {
+    let b = read_bytes(bytes, offset, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, GctconvError> {
+    let b = read_bytes(bytes, offset, 4)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Checks the file, or the sub-file at `base` in it, starts with `magic`.
+pub(crate) fn check_magic_at(
+    bytes: &[u8],
+    base: usize,
+    magic: &[u8; 4],
+) -> Result<(), GctconvError> {
+    check_magic(bytes.get(base..).unwrap_or_default(), magic)
+}
+
+pub(crate) fn check_magic(bytes: &[u8], magic: &[u8; 4]) -> Result<(), GctconvError> {
+    if bytes.starts_with(magic) {
+        Ok(())
+    } else {
+        Err(GctconvError::BadMagic { expected: *magic })
+    }
+}
+
+/// Reads a length-prefixed string: its length as an int at `offset`,
+/// followed by its characters.
+pub(crate) fn read_string(bytes: &[u8], offset: usize) -> Result<String, GctconvError> {
+    let len = read_u32(bytes, offset)? as usize;
+    let name = read_bytes(bytes, offset.saturating_add(4), len)?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+/// Reads the name of the TEX0 or PLT0 at `base` through the name offset in
+/// its header at `name_field`, which points past the name's length at its
+/// first character. Inside a BRRES the name is in the archive's string
+/// table, which can come before the sub-file, so the offset is signed.
+pub(crate) fn read_name(
+    bytes: &[u8],
+    base: usize,
+    name_field: usize,
+) -> Result<String, GctconvError> {
+    let field = base.saturating_add(name_field);
+    let name_offset = read_u32(bytes, field)?;
+    let len_offset = base as i64 + name_offset as i32 as i64 - 4;
+    match usize::try_from(len_offset) {
+        Ok(len_offset) => read_string(bytes, len_offset),
+        Err(_) => Err(GctconvError::BadHeaderValue {
+            offset: field,
+            value: name_offset as usize,
+        }),
+    }
+}
+
+/// Finds the data and name of the TEX0 or PLT0 at `base` through the offsets
+/// in its header, so neither depends on the other's length. The data runs
+/// from the data offset to the end of the declared size, and the name offset
+/// is read from `name_field`.
+pub(crate) fn read_data_and_name(
+    bytes: &[u8],
+    base: usize,
+    name_field: usize,
+) -> Result<(&[u8], String), GctconvError> {
+    let size = read_u32(bytes, base.saturating_add(0x04))? as usize;
+    let data_offset = read_u32(bytes, base.saturating_add(0x10))? as usize;
+
+    let data_size = match size.checked_sub(data_offset) {
+        Some(s) if data_offset >= 0x10 => s,
+        _ => {
+            return Err(GctconvError::BadHeaderValue {
+                offset: base.saturating_add(0x10),
+                value: data_offset,
+            })
+        }
+    };
+    let data = read_bytes(bytes, base.saturating_add(data_offset), data_size)?;
+    let name = read_name(bytes, base, name_field)?;
+    Ok((data, name))
+}
+
+/// Builds the name block that trails a TEX0 or PLT0: the name length as an
+/// int, followed by the name itself and some padding.
+pub(crate) fn name_block(name: &str) -> Result<Vec<u8>, GctconvError> {
+    if !name.is_ascii() {
+        return Err(GctconvError::NonAsciiName(name.to_owned()));
+    }
+
+    let name_bytes = name.as_bytes();
+    let name_len = match u8::try_from(name_bytes.len()) {
+        Ok(i) => i,
+        Err(_) => return Err(GctconvError::NameTooLong(name.to_owned())),
+    };
+    let mut block = vec![0_u8; 3]; // three 0 bytes
+    block.push(name_len);
+    block.extend(name_bytes);
+    let end_pad_len = if block.len().is_multiple_of(4) {
+        4
+    } else {
+        block.len() % 4
+    };
+    // name padding is padded up to the next 4th byte
+    block.extend(vec![0; end_pad_len]);
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A colour index texture in every format, so each has data, a palette
+    /// and a name to find.
+    fn files() -> (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) {
+        let rgba: Vec<u8> = (0..64).flat_map(|i| [i * 4, 0, 255 - i * 4, 255]).collect();
+        let options = EncodeOptions::default();
+        let gct = Gct::from_rgba(EncodingType::Ci4, 8, 8, &rgba, &options).unwrap();
+        let mut tex0 = Tex0::from(&gct);
+        tex0.name = "texture".to_owned();
+        let mut plt0 = Plt0::try_from(&gct).unwrap();
+        plt0.name = "texture".to_owned();
+        let brres = Brres::from_textures(vec![tex0.clone()], vec![plt0.clone()]);
+        (
+            gct.to_bytes(),
+            tex0.to_bytes().unwrap(),
+            plt0.to_bytes().unwrap(),
+            brres.to_bytes().unwrap(),
+        )
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Parses every prefix of `bytes`, which mustn't panic, and checks those
+    /// shorter than `needed` are errors.
+    fn truncate<T>(bytes: &[u8], needed: usize, parse: fn(&[u8]) -> Result<T, GctconvError>) {
+        for len in 0..bytes.len() {
+            let result = parse(&bytes[..len]);
+            assert!(len >= needed || result.is_err(), "parsed {:#x} bytes", len);
+        }
+        assert!(parse(bytes).is_ok());
+    }
+
+    #[test]
+    fn truncated_files() {
+        let (gct, tex0, plt0, brres) = files();
+        // GCTs run to the end of the file, so only the header and palette
+        // are needed
+        truncate(
+            &gct,
+            HEADER_SIZE + EncodingType::Ci4.palette_size(),
+            Gct::parse,
+        );
+        // the name of a TEX0 or PLT0 comes last, followed by padding
+        truncate(&tex0, tex0.len() - 4, Tex0::parse);
+        truncate(&plt0, plt0.len() - 4, Plt0::parse);
+        truncate(&brres, brres.len() - 4, Brres::parse);
+    }
+
+    #[test]
+    fn offsets_past_the_end() {
+        let (_, tex0, plt0, brres) = files();
+        for bytes in [&tex0, &plt0] {
+            // data offset, then the size it runs to
+            for (offset, value) in [(0x10, 0xFFFF_FFF0), (0x04, 0x7FFF_FFFF)] {
+                let mut bad = bytes.clone();
+                set_u32(&mut bad, offset, value);
+                assert!(Tex0::parse(&bad).is_err() && Plt0::parse(&bad).is_err());
+            }
+            // the name offset, both ways
+            for value in [0x7FFF_FFFF, 0x8000_0000] {
+                let mut bad = bytes.clone();
+                set_u32(&mut bad, 0x14, value);
+                assert!(Tex0::parse(&bad).is_err() && Plt0::parse(&bad).is_err());
+            }
+        }
+
+        // the root, then the first folder's group and its name
+        let root = u16::from_be_bytes([brres[0x0C], brres[0x0D]]) as usize;
+        let folder = root + 0x08 + 0x18;
+        let mut bad = brres.clone();
+        bad[0x0C..0x0E].copy_from_slice(&0xFFF0_u16.to_be_bytes());
+        assert!(Brres::parse(&bad).is_err());
+        for offset in [folder + 0x0C, folder + 0x08] {
+            let mut bad = brres.clone();
+            set_u32(&mut bad, offset, 0x7FFF_FFFF);
+            assert!(Brres::parse(&bad).is_err());
+        }
+        // an entry count running past the group
+        let mut bad = brres.clone();
+        set_u32(&mut bad, root + 0x08 + 0x04, 0xFFFF_FFFF);
+        assert!(Brres::parse(&bad).is_err());
+    }
+}
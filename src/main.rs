@@ -1,676 +1,697 @@
 use std::{
-    convert::{TryFrom, TryInto},
-    io::{Seek, SeekFrom},
-};
-use std::{
-    env,
-    fs::{self, File, Metadata},
-    io::{Error, Read},
-    path::Path,
+    convert::TryFrom,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
     process,
 };
 
-use num_traits::{FromPrimitive, ToPrimitive};
-
-#[macro_use]
-extern crate num_derive;
-
-#[repr(u8)]
-#[derive(FromPrimitive, ToPrimitive)]
-enum EncodingType {
-    I4 = 0x00,
-    I8 = 0x01,
-    Ia4 = 0x02,
-    Ia8 = 0x03,
-    Rgb565 = 0x04,
-    Rgb5A3 = 0x05,
-    Rgba32 = 0x06,
-    Ci4 = 0x08,
-    Ci8 = 0x09,
-    Ci14x2 = 0x0A,
-    Cmpr = 0x0E,
-}
+use gctconv::{
+    png_to_rgba, rgba_to_png, Brres, EncodeOptions, FileInfo, FileKind, Gct, GctconvError, Plt0,
+    Problem, SubFile, Tex0, PALETTES, TEXTURES,
+};
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
+mod batch;
+mod cli;
+mod json;
+mod report;
 
-    if args.len() < 3 {
-        println!("Not enough arguments\n");
-        usage();
-        process::exit(exitcode::USAGE);
-    }
+use batch::Job;
+use cli::{Args, Command, Layout};
+use report::{Report, Texture};
 
-    let mode_request = &args[1];
-    match mode_request.as_str() {
-        "-tex0" => to_tex0(args),
-        "-gct" => to_gct(args),
-        _ => {
-            println!("Invalid operating mode.\n");
-            usage();
-            process::exit(exitcode::USAGE);
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => usage_error(&message),
+    };
+
+    if args.command == Command::Replace {
+        // the inputs are an archive, a name and a file rather than files to
+        // convert one by one
+        let job = Job {
+            input: args.inputs[0].clone(),
+            palette: None,
+            folder: PathBuf::new(),
+        };
+        let mut report = Report::default();
+        let result = replace(&args, &job, &mut report);
+        print_report(&args, &job, &report, &result);
+        if let Err(error) = result {
+            if args.json {
+                process::exit(exit_code(&error));
+            }
+            fail(&error);
         }
+        return;
     }
-}
 
-fn usage() {
-    println!("Usage:");
-    println!("gctconv -tex0 file.gct");
-    println!("gctconv -gct file.tex0 [file.plt0]");
-}
-
-fn to_tex0(args: Vec<String>) {
-    let file_path_string = &args[2];
-    let path = Path::new(file_path_string);
-    let filename = path.file_name();
-    let fn_no_ext = path.file_stem();
-    let fn_string;
-    let fs_string;
-    match filename {
-        Some(fname) => match fname.to_str() {
-            Some(str) => fn_string = str,
-            None => fn_string = "[filename error]",
+    let jobs = match batch::collect(&args) {
+        Ok(jobs) => jobs,
+        Err(error) => fail(&error),
+    };
+    if jobs.is_empty() {
+        usage_error("No files to convert.");
+    }
+    if args.command == Command::ExtractGct && args.output.is_some() {
+        usage_error("-o can't be used with extract-gct, use --out-dir instead.");
+    }
+    if jobs.len() > 1 && args.output.is_some() && args.command != Command::ToBrres {
+        usage_error("-o can only be used with a single input file, use --out-dir instead.");
+    }
+
+    // files are converted in parallel, but everything they print comes out
+    // in input order
+    let mut failures = Vec::new();
+    let mut packed = Vec::new();
+    batch::run_parallel(
+        &jobs,
+        args.jobs,
+        |job| {
+            let mut report = Report::default();
+            let result = run(&args, job, &mut report);
+            (report, result)
         },
-        None => fn_string = "[filename error]",
-    }
-
-    match fn_no_ext {
-        Some(fname) => match fname.to_str() {
-            Some(str) => fs_string = str,
-            None => fs_string = "[filename error]",
+        |job, (mut report, result)| {
+            packed.extend(report.packed.take());
+            print_report(&args, job, &report, &result);
+            if let Err(error) = result {
+                failures.push((job, error));
+            }
         },
-        None => fs_string = "[filename error]",
-    }
-
-    let md;
-    let md_result = fs::metadata(path);
-    match md_result {
-        Ok(m) => md = m,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("GCT Metadata Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
+    );
+
+    if args.command == Command::ToBrres && failures.is_empty() {
+        let count = packed.len();
+        let mut report = Report::default();
+        let result = write_brres(&args, packed, &mut report);
+        if args.json {
+            println!("{}", report.archive_json(&result));
+        } else if let Some(path) = report.outputs.first() {
+            let textures = if count == 1 { "texture" } else { "textures" };
+            println!("Packed {} {} into {}", count, textures, path.display());
         }
-    }
-    let filesize = md.len();
-
-    let mut fs_int;
-    let fs_int_result = u32::try_from(filesize);
-    match fs_int_result {
-        Ok(i) => fs_int = i,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("GCT Too Big: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
+        if let Err(error) = result {
+            if args.json {
+                process::exit(exit_code(&error));
+            }
+            fail(&error);
         }
     }
 
-    let gct = File::open(path);
-    let mut gct_file;
-    match gct {
-        Ok(f) => gct_file = f,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("GCT Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
+    if args.json || jobs.len() == 1 {
+        if let Some((_, error)) = failures.first() {
+            if args.json {
+                process::exit(exit_code(error));
+            }
+            fail(error);
         }
+        return;
     }
 
-    let seek_result = gct_file.seek(SeekFrom::Start(0x10));
-    match seek_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Seek Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
+    // a summary of the whole batch, rather than stopping at the first error
+    println!(
+        "\n{} succeeded, {} failed",
+        jobs.len() - failures.len(),
+        failures.len()
+    );
+    for (job, error) in &failures {
+        println!("    {}: {}", job.input.display(), error);
     }
-
-    let mut width_bytes = [0; 2];
-    let width_result = gct_file.read_exact(&mut width_bytes);
-    match width_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read width: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
+    if let Some((_, error)) = failures.first() {
+        process::exit(exit_code(error));
     }
+}
 
-    let mut height_bytes = [0; 2];
-    let height_result = gct_file.read_exact(&mut height_bytes);
-    match height_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read height: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
+fn print_report(args: &Args, job: &Job, report: &Report, result: &Result<(), GctconvError>) {
+    if args.json {
+        let command = report.command.unwrap_or(args.command);
+        println!("{}", report.to_json(command, job, result));
+    } else {
+        for line in report.lines(job) {
+            println!("{}", line);
         }
     }
+}
 
-    let mut enc_byte = [0; 1];
-    let enc_result = gct_file.read_exact(&mut enc_byte);
-    match enc_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read encoding type: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
+/// Converts a single file, noting what it read and wrote in `report`.
+fn run(args: &Args, job: &Job, report: &mut Report) -> Result<(), GctconvError> {
+    let bytes = fs::read(&job.input)?;
+    if args.command == Command::Info {
+        return info(&bytes, report);
+    }
+
+    // the magic decides what the file is, not its extension
+    let kind = FileKind::detect(&bytes).ok_or(GctconvError::UnknownFormat)?;
+    let command = args.command.for_kind(kind)?;
+    report.command = Some(command);
+    match command {
+        Command::ToTex0 => to_tex0(args, job, &bytes, kind, report),
+        Command::ToGct => to_gct(args, job, &bytes, kind, report),
+        Command::ToPng => to_png(args, job, &bytes, kind, report),
+        Command::ToBrres => to_brres(args, job, &bytes, kind, report),
+        Command::ExtractGct => extract_gct(args, job, &bytes, report),
+        Command::Check => check(job, &bytes, kind, report),
+        Command::Replace => unreachable!(),
+        Command::Convert | Command::Info => unreachable!(),
     }
+}
 
-    let mut rgb_byte = [0; 1];
-    let enc_result = gct_file.read_exact(&mut rgb_byte);
-    match enc_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read rgb type: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+fn usage_error(message: &str) -> ! {
+    println!("{}\n", message);
+    usage();
+    process::exit(exitcode::USAGE);
+}
 
-    let seek_result = gct_file.seek(SeekFrom::Start(0x40));
-    match seek_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Seek Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+fn fail(error: &GctconvError) -> ! {
+    println!("Error: {}", error);
+    process::exit(exit_code(error));
+}
 
-    let mut rest_of_file = Vec::new();
-    let read_result = gct_file.read_to_end(&mut rest_of_file);
-    match read_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read rest of file: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+fn usage() {
+    println!("Usage:");
+    println!("gctconv convert file... [encoding options] [output options]");
+    println!("gctconv to-tex0 file.gct [output options]");
+    println!("gctconv to-tex0 file.png --format <encoding> [encoding options] [output options]");
+    println!("gctconv to-gct file.tex0 [file.plt0] [output options]");
+    println!("gctconv to-gct file.png --format <encoding> [encoding options] [output options]");
+    println!("gctconv to-png file.gct [output options]");
+    println!("gctconv to-png file.tex0 [file.plt0] [output options]");
+    println!("gctconv to-brres file... [encoding options] [output options]");
+    println!("gctconv extract-gct file.brres... [output options]");
+    println!("gctconv replace file.brres <texture> file.gct|file.png [encoding options]");
+    println!("        [--force] [output options]");
+    println!("gctconv check file...");
+    println!("gctconv info file...");
+    println!();
+    println!("convert goes by what each file starts with: GCTs become TEX0s, and TEX0s and");
+    println!("PNGs become GCTs. Files that aren't what the command reads are rejected.");
+    println!("to-brres packs every GCT, TEX0 and PNG into one archive, textures.brres in");
+    println!("the output folder or the -o path. extract-gct writes a GCT for every texture");
+    println!("in a BRRES, into a folder named after it. replace swaps one texture in a");
+    println!("BRRES, leaving the rest as it is, and refuses a texture of another size or");
+    println!("encoding unless given --force. A texture name starting with - goes after");
+    println!("--, which ends the options. check reports textures whose data doesn't match");
+    println!("their size and encoding, that are too large for the hardware, or that have");
+    println!("mip levels without being a power of two wide and high; every other command");
+    println!("warns about the same problems.");
+    println!();
+    println!("Any input can also be a folder or a glob such as \"textures/**/*.gct\", which");
+    println!("is converted recursively, keeping its folder structure in the output. TEX0s");
+    println!("are paired with the PLT0 of the same name in Palettes(NW4R) or next to them.");
+    println!();
+    println!("Output options:");
+    println!("    -o, --output <file>                 write to this exact path");
+    println!("    --out-dir <dir>                     write into this folder, output by default");
+    println!("    --layout brawlbox|flat              put TEX0s and PLT0s in Textures(NW4R)");
+    println!("                                        and Palettes(NW4R) folders, or not");
+    println!("    --tex0-version 1|2|3                write TEX0s of this version, 1 by default");
+    println!("    --mip-levels <n>|full               generate a mip chain of n images, the");
+    println!("                                        texture included, or as many as fit");
+    println!("    --mip-filter box|triangle|lanczos|kaiser");
+    println!("                                        filter the mip levels are made with,");
+    println!("                                        box by default");
+    println!("    -j, --jobs <n>                      convert this many files at once, by");
+    println!("                                        default one per CPU core");
+    println!("    --json                              report on each file as a line of JSON");
+    println!();
+    println!("Encodings: i4, i8, ia4, ia8, rgb565, rgb5a3, rgba32, ci4, ci8, ci14x2, cmpr");
+    println!("Encoding options:");
+    println!("    --quality fast|high                 CMPR endpoint search");
+    println!("    --palette-format ia8|rgb565|rgb5a3  colour index palette format");
+    println!("    --dither none|floyd-steinberg|atkinson|bayer");
+    println!("                                        dithering for i4, ia4, rgb565, rgb5a3");
+    println!("                                        and colour index encodings, none by");
+    println!("                                        default");
+    println!("    --fit pad|pad-transparent|scale|crop");
+    println!("                                        make PNGs a power of two wide and high,");
+    println!("                                        and at most 1024, before encoding them");
+    println!("    --fit-filter box|triangle|lanczos|kaiser");
+    println!("                                        filter --fit scales with, box by default");
+}
 
-    let tex0_ascii = "TEX0";
-    if !tex0_ascii.is_ascii() {
-        println!("\"{}\" isn't ascii\n", tex0_ascii);
-        usage();
-        process::exit(exitcode::DATAERR);
+fn exit_code(error: &GctconvError) -> exitcode::ExitCode {
+    match error {
+        GctconvError::Io(e) if e.kind() == ErrorKind::NotFound => exitcode::NOINPUT,
+        GctconvError::Io(_) => exitcode::IOERR,
+        _ => exitcode::DATAERR,
     }
+}
 
-    // header starts with "TEX0"
-    let mut header = tex0_ascii.as_bytes().to_owned();
-    // header == ["T", "E", "X", "0"]
-
-    // then the filesize, big endian
-    fs_int -= encoding_palette_size_from_byte(enc_byte);
-    let fs_bytes = fs_int.to_be_bytes().to_vec();
-    header.extend(fs_bytes);
-    // header == [...[0x04], FS_1, FS_2, FS_3, FS_4]
-
-    // then tex0 version as int, 1 in our case
-    let four_byte_1 = 1_u32.to_be_bytes().to_vec();
-    header.extend(&four_byte_1);
-    // header == [...[0x08], 1_byte_1, 1_byte_2, 1_byte_3, 1_byte_4]
-
-    // then int 0
-    let four_byte_0 = 0_u32.to_be_bytes().to_vec();
-    header.extend(&four_byte_0);
-    // header == [...[0x0C], 0_byte, 0_byte, 0_byte, 0_byte]
-
-    // then 'idk'?? just hex 0x40 as int
-    let idk = 0x40_u32.to_be_bytes().to_vec();
-    header.extend(idk);
-    // header == [...[0x10], 0x40_1, 0x40_2, 0x40_3, 0x40_4]
-
-    // then 0x4 + filesize, as int
-    let fs_plus_4 = fs_int + 0x4;
-    let fs_p4_bytes = fs_plus_4.to_be_bytes().to_vec();
-    header.extend(fs_p4_bytes);
-    // header == [...[0x14], FS_P4_1, FS_P4_2, FS_P4_3, FS_P4_4]
-
-    match FromPrimitive::from_u8(enc_byte[0]) {
-        // int 1 if CI4 or CI8
-        Some(EncodingType::Ci4) | Some(EncodingType::Ci8) => header.extend(&four_byte_1),
-        // otherwise int 0
-        None | Some(_) => header.extend(&four_byte_0),
+fn file_stem(path: &Path) -> &str {
+    match path.file_stem() {
+        Some(fname) => match fname.to_str() {
+            Some(str) => str,
+            None => "[filename error]",
+        },
+        None => "[filename error]",
     }
-    // header == [...[0x18], 0/1_byte, 0/1_byte, 0/1_byte, 0/1_byte]
-
-    // width as short, height as short, enc as byte
-    header.extend(width_bytes.to_vec());
-    // header == [...[0x1A], width_1, width_2]
-    header.extend(height_bytes.to_vec());
-    // header == [...[0x1C], height_1, height_2]
-
-    // padding for enc byte
-    header.extend([0; 3].to_vec());
-    // header == [...[0x20], 0_byte, 0_byte, 0_byte]
-
-    // encoding byte
-    header.extend(enc_byte.to_vec());
-    // header == [...[0x23], enc_byte]
-
-    // mipmap count + 1 as int; no mipmaps, so just 1
-    header.extend(&four_byte_1);
-    // header == [...[0x24], 1_byte_1, 1_byte_2, 1_byte_3, 1_byte_4]
-
-    let padding = [0_u8; 24].to_vec();
-    header.extend(padding);
-    // header is now padded to 0x40
-
-    let mut plt0_file = match FromPrimitive::from_u8(enc_byte[0]) {
-        Some(EncodingType::Ci4) => {
-            // if CI4, extract palette data
-            let palette_data = rest_of_file
-                .split_off(rest_of_file.len() - encoding_palette_size_usize(EncodingType::Ci4));
-
-            const PLT0_HEADER: [u8; 0x40] = [
-                0x50, 0x4C, 0x54, 0x30, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x02,
-                0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            ];
-
-            let mut plt0_file = PLT0_HEADER.to_vec();
-            plt0_file[0x1B] = rgb_byte[0];
-            plt0_file.extend(palette_data);
-            plt0_file
-        }
-        Some(EncodingType::Ci8) => {
-            let palette_data = rest_of_file
-                .split_off(rest_of_file.len() - encoding_palette_size_usize(EncodingType::Ci8));
-
-            const PLT0_HEADER: [u8; 0x40] = [
-                0x50, 0x4C, 0x54, 0x30, 0x00, 0x00, 0x02, 0x40, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x02, 0x44, 0x00, 0x00, 0x00, 0x02,
-                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            ];
-
-            let mut plt0_file = PLT0_HEADER.to_vec();
-            plt0_file[0x1B] = rgb_byte[0];
-            plt0_file.extend(palette_data);
-            plt0_file
-        }
-        None | Some(_) => Vec::new(),
-    };
-
-    let mut tex0_file = header;
-    tex0_file.extend(rest_of_file);
+}
 
-    let mut footer = vec![0_u8; 3]; // three 0 bytes
-    if !fs_string.is_ascii() {
-        println!("\"{}\" isn't ascii\n", fs_string);
-        usage();
-        process::exit(exitcode::DATAERR);
+fn has_extension(path: &Path, extension: &str) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case(extension),
+        None => false,
     }
+}
 
-    let stem_bytes = fs_string.as_bytes().to_owned();
-    let stem_length = stem_bytes.len();
-    let sl_byte;
-    let sl_byte_result = u8::try_from(stem_length);
-    match sl_byte_result {
-        Ok(i) => sl_byte = i,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("GCT Filename Too Big: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
+/// Where the output made from a job goes: the `-o` path if there is one,
+/// otherwise `<stem>.<extension>` in the job's folder of the output
+/// directory, under `nw4r_folder` with the BrawlBox layout.
+///
+/// A PLT0 written alongside a TEX0 given with `-o` goes next to it, with
+/// the same name.
+fn output_path(args: &Args, job: &Job, nw4r_folder: Option<&str>, extension: &str) -> PathBuf {
+    if let Some(output) = &args.output {
+        if extension == "plt0" {
+            return output.with_extension(extension);
         }
+        return output.clone();
     }
 
-    footer.extend(vec![sl_byte]); // vec.extend_one is unstable, so have to vec![]
-    footer.extend(stem_bytes);
-    let end_pad_len = if footer.len() % 4 == 0 {
-        4
-    } else {
-        footer.len() % 4
-    };
-    // footer name padding is padded up to the next 4th byte
-    footer.extend(vec![0; end_pad_len]);
-
-    tex0_file.extend(&footer);
-
-    let tex0_path = format!("output/Textures(NW4R)/{}.tex0", fs_string);
-    let write_result = fs::write(tex0_path, tex0_file);
-    match write_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to write TEX0 file: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
+    let mut path = args.out_dir.join(&job.folder);
+    if let (Some(folder), Layout::BrawlBox) = (nw4r_folder, args.layout) {
+        path.push(folder);
     }
+    path.push(format!("{}.{}", file_stem(&job.input), extension));
+    path
+}
 
-    if plt0_file.is_empty() {
-        return;
+/// Writes `contents` to `path`, creating any folders it needs first.
+fn write_output(path: PathBuf, contents: &[u8], report: &mut Report) -> Result<(), GctconvError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    fs::write(&path, contents)?;
+    report.outputs.push(path);
+    Ok(())
+}
 
-    plt0_file.extend(footer);
-
-    let plt0_path = format!("output/Palettes(NW4R)/{}.plt0", fs_string);
-    let write_result = fs::write(plt0_path, plt0_file);
-    match write_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to write PLT0 file: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+/// Encodes a PNG with the encoding given by `--format`. Without one only
+/// this job fails, with `GctconvError::MissingFormat`, so the rest of a
+/// batch is still converted.
+fn gct_from_png(png_file: &[u8], args: &Args) -> Result<Gct, GctconvError> {
+    let encoding = args.format.ok_or(GctconvError::MissingFormat)?;
+
+    let (width, height, rgba) = png_to_rgba(png_file)?;
+    Gct::from_rgba(encoding, width, height, &rgba, &args.options)
 }
 
-fn to_gct(args: Vec<String>) {
-    const TREY_PAD: [u8; 0x20] = [
-        0x20, 0x74, 0x72, 0x65, 0x79, 0x61, 0x72, 0x63, 0x68, 0x67, 0x63, 0x74, 0x2E, 0x38, 0x62,
-        0x69, 0x20, 0x76, 0x32, 0x2E, 0x32, 0x2E, 0x34, 0x23, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00,
-    ];
-
-    let file_path_string = &args[2];
-    let path = Path::new(file_path_string);
-    let filename = path.file_name();
-    let fn_no_ext = path.file_stem();
-    let fn_string;
-    let fs_string;
-    match filename {
-        Some(fname) => match fname.to_str() {
-            Some(str) => fn_string = str,
-            None => fn_string = "[filename error]",
-        },
-        None => fn_string = "[filename error]",
+/// Gives the texture a mip chain if `--mip-levels` asked for one.
+fn add_mipmaps(args: &Args, gct: &mut Gct) -> Result<(), GctconvError> {
+    match args.mip_levels {
+        Some(levels) => gct.generate_mipmaps(levels, args.mip_filter, &args.options),
+        None => Ok(()),
     }
+}
 
-    match fn_no_ext {
-        Some(fname) => match fname.to_str() {
-            Some(str) => fs_string = str,
-            None => fs_string = "[filename error]",
-        },
-        None => fs_string = "[filename error]",
-    }
+/// Notes everything wrong with a texture, which is still written.
+fn warn_problems(report: &mut Report, problems: Vec<Problem>) {
+    report
+        .warnings
+        .extend(problems.iter().map(Problem::to_string));
+}
 
-    let mut tex0_file;
-    let tex0_result = File::open(path);
-    match tex0_result {
-        Ok(f) => tex0_file = f,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("GCT Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
-        }
-    }
+fn to_tex0(
+    args: &Args,
+    job: &Job,
+    bytes: &[u8],
+    kind: FileKind,
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let fs_string = file_stem(&job.input);
+
+    let mut gct = match kind {
+        FileKind::Png => gct_from_png(bytes, args)?,
+        _ => Gct::parse(bytes)?,
+    };
+    add_mipmaps(args, &mut gct)?;
+    report.texture = Some(Texture::of_gct(&gct));
+    warn_problems(report, gct.validate());
 
-    let seek_result = tex0_file.seek(SeekFrom::Start(0x1C));
-    match seek_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Seek Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+    let mut tex0 = Tex0::from(&gct);
+    tex0.name = fs_string.to_owned();
+    tex0.version = args.tex0_version;
+    let tex0_path = output_path(args, job, Some(TEXTURES), "tex0");
+    write_output(tex0_path, &tex0.to_bytes()?, report)?;
 
-    let mut width_bytes = [0; 2];
-    let width_result = tex0_file.read_exact(&mut width_bytes);
-    match width_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read width: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
+    if !gct.encoding.has_palette() {
+        return Ok(());
     }
 
-    let mut height_bytes = [0; 2];
-    let height_result = tex0_file.read_exact(&mut height_bytes);
-    match height_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read height: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+    let mut plt0 = Plt0::try_from(&gct)?;
+    plt0.name = fs_string.to_owned();
+    let plt0_path = output_path(args, job, Some(PALETTES), "plt0");
+    write_output(plt0_path, &plt0.to_bytes()?, report)
+}
 
-    let seek_result = tex0_file.seek(SeekFrom::Start(0x23));
-    match seek_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Seek Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
+fn to_gct(
+    args: &Args,
+    job: &Job,
+    bytes: &[u8],
+    kind: FileKind,
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let gct_path = output_path(args, job, None, "gct");
+
+    if kind == FileKind::Png {
+        let mut gct = gct_from_png(bytes, args)?;
+        add_mipmaps(args, &mut gct)?;
+        report.texture = Some(Texture::of_gct(&gct));
+        warn_problems(report, gct.validate());
+        return write_output(gct_path, &gct.to_bytes(), report);
+    }
+
+    let tex0 = Tex0::parse(bytes)?;
+
+    let mut gct = match (&job.palette, tex0.encoding.has_palette()) {
+        (None, true) => return Err(GctconvError::MissingPalette),
+        (Some(plt0_path), true) => {
+            let plt0 = Plt0::parse(&fs::read(plt0_path)?)?;
+            Gct::from((&tex0, &plt0))
         }
-    }
+        (_, false) => Gct::from(&tex0),
+    };
+    add_mipmaps(args, &mut gct)?;
+    report.texture = Some(Texture::of_gct(&gct));
+    warn_problems(report, gct.validate());
 
-    let mut enc_byte = [0; 1];
-    let enc_result = tex0_file.read_exact(&mut enc_byte);
-    match enc_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read encoding type: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+    write_output(gct_path, &gct.to_bytes(), report)
+}
 
-    let seek_result = tex0_file.seek(SeekFrom::Start(0x40));
-    match seek_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Seek Error: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+fn to_png(
+    args: &Args,
+    job: &Job,
+    bytes: &[u8],
+    kind: FileKind,
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let (width, height, rgba) = if kind == FileKind::Tex0 {
+        let tex0 = Tex0::parse(bytes)?;
+        let plt0 = match (&job.palette, tex0.encoding.has_palette()) {
+            (Some(plt0_path), true) => Some(Plt0::parse(&fs::read(plt0_path)?)?),
+            _ => None,
+        };
+        report.texture = Some(Texture::of_tex0(&tex0, plt0.as_ref()));
+        warn_problems(report, tex0.validate(plt0.as_ref()));
+        (tex0.width, tex0.height, tex0.to_rgba(plt0.as_ref())?)
+    } else {
+        let gct = Gct::parse(bytes)?;
+        report.texture = Some(Texture::of_gct(&gct));
+        warn_problems(report, gct.validate());
+        (gct.width, gct.height, gct.to_rgba()?)
+    };
 
-    let mut rest_of_file = Vec::new();
-    let read_result = tex0_file.read_to_end(&mut rest_of_file);
-    match read_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to read rest of file: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
-        }
-    }
+    let png_path = output_path(args, job, None, "png");
+    write_output(png_path, &rgba_to_png(width, height, &rgba)?, report)
+}
 
-    let name_len = 4 + fs_string.len();
-    let end_pad_len = if name_len % 4 == 0 { 4 } else { name_len % 4 };
-    let footer_len = name_len + end_pad_len;
-    rest_of_file.truncate(rest_of_file.len() - footer_len);
-
-    let data_len;
-    let data_len_result = u32::try_from(rest_of_file.len());
-    match data_len_result {
-        Ok(i) => data_len = i,
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Data Block Too Big: {}\n", error_string);
-            usage();
-            process::exit(exitcode::NOINPUT);
+/// Converts a file to the TEX0, and PLT0 if it has a palette, that
+/// `to-brres` packs. TEX0s keep the name they have, while GCTs and PNGs are
+/// named after the file.
+fn to_brres(
+    args: &Args,
+    job: &Job,
+    bytes: &[u8],
+    kind: FileKind,
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let (tex0, plt0) = if kind == FileKind::Tex0 {
+        let mut tex0 = Tex0::parse(bytes)?;
+        if tex0.name.is_empty() {
+            tex0.name = file_stem(&job.input).to_owned();
         }
-    }
-
-    const GCT_HEADER_START: [u8; 12] = [
-        0x47, 0x43, 0x4E, 0x54, 0x00, 0x00, 0x00, 0x03, 0x00, 0x40, 0x00, 0x00,
-    ];
-
-    let mut header = GCT_HEADER_START.to_vec();
-    header.extend(data_len.to_be_bytes().to_vec());
-    header.extend(width_bytes.to_vec());
-    header.extend(height_bytes.to_vec());
-    header.extend(enc_byte.to_vec());
-    header.extend(if encoding_has_palette(enc_byte) {
-        vec![0x02]
+        let plt0 = match (&job.palette, tex0.encoding.has_palette()) {
+            (None, true) => return Err(GctconvError::MissingPalette),
+            (Some(plt0_path), true) => Some(Plt0::parse(&fs::read(plt0_path)?)?),
+            (_, false) => None,
+        };
+        report.texture = Some(Texture::of_tex0(&tex0, plt0.as_ref()));
+        (tex0, plt0)
     } else {
-        vec![0x01]
+        let mut gct = match kind {
+            FileKind::Png => gct_from_png(bytes, args)?,
+            _ => Gct::parse(bytes)?,
+        };
+        add_mipmaps(args, &mut gct)?;
+        report.texture = Some(Texture::of_gct(&gct));
+
+        let mut tex0 = Tex0::from(&gct);
+        tex0.name = file_stem(&job.input).to_owned();
+        tex0.version = args.tex0_version;
+        let plt0 = if gct.encoding.has_palette() {
+            Some(Plt0::try_from(&gct)?)
+        } else {
+            None
+        };
+        (tex0, plt0)
+    };
+
+    // the palette goes by the same name as its texture
+    let plt0 = plt0.map(|plt0| Plt0 {
+        name: tex0.name.clone(),
+        ..plt0
     });
-    header.extend(vec![0; 10]);
-    header.extend(TREY_PAD.to_vec());
-
-    let mut gct_file = header;
-    gct_file.extend(rest_of_file);
-
-    match (args.len() < 4, encoding_has_palette(enc_byte)) {
-        (true, true) => println!("this encoding should have a palette file as an argument!!"),
-        (false, true) => {
-            let file_path_string = &args[3];
-            let path = Path::new(file_path_string);
-            let filename = path.file_name();
-            let fn_no_ext = path.file_stem();
-            let fn_string;
-            let fs_string;
-            match filename {
-                Some(fname) => match fname.to_str() {
-                    Some(str) => fn_string = str,
-                    None => fn_string = "[filename error]",
-                },
-                None => fn_string = "[filename error]",
-            }
+    // a missing palette has been warned about already
+    let problems = tex0.validate(plt0.as_ref());
+    warn_problems(
+        report,
+        problems
+            .into_iter()
+            .filter(|p| !matches!(p, Problem::MissingPalette(_)))
+            .collect(),
+    );
+    report.packed = Some((tex0, plt0));
+    Ok(())
+}
 
-            match fn_no_ext {
-                Some(fname) => match fname.to_str() {
-                    Some(str) => fs_string = str,
-                    None => fs_string = "[filename error]",
-                },
-                None => fs_string = "[filename error]",
-            }
+/// Packs everything `to-brres` converted into one archive, written to the
+/// `-o` path or `textures.brres` in the output directory.
+fn write_brres(
+    args: &Args,
+    packed: Vec<(Tex0, Option<Plt0>)>,
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let (textures, palettes): (Vec<_>, Vec<_>) = packed.into_iter().unzip();
+    let brres = Brres::from_textures(textures, palettes.into_iter().flatten().collect());
+
+    let path = match &args.output {
+        Some(output) => output.clone(),
+        None => args.out_dir.join("textures.brres"),
+    };
+    write_output(path, &brres.to_bytes()?, report)
+}
 
-            let mut plt0_file;
-            let plt0_result = File::open(path);
-            match plt0_result {
-                Ok(f) => plt0_file = f,
-                Err(error) => {
-                    let error_string = error.to_string();
-                    println!("GCT Error: {}\n", error_string);
-                    usage();
-                    process::exit(exitcode::NOINPUT);
-                }
-            }
+/// Writes a GCT for every TEX0 in a BRRES, with the palette of the PLT0 of
+/// the same name, into a folder named after the archive.
+fn extract_gct(
+    args: &Args,
+    job: &Job,
+    bytes: &[u8],
+    report: &mut Report,
+) -> Result<(), GctconvError> {
+    let brres = Brres::parse(bytes)?;
+    let textures = match brres.folder(TEXTURES) {
+        Some(folder) => &folder.entries[..],
+        None => &[],
+    };
+    if textures.is_empty() {
+        report.warnings.push(format!("no textures in {}", TEXTURES));
+    }
+    let palettes = match brres.folder(PALETTES) {
+        Some(folder) => &folder.entries[..],
+        None => &[],
+    };
 
-            let seek_result = plt0_file.seek(SeekFrom::Start(0x1B));
-            match seek_result {
-                Ok(_) => {}
-                Err(error) => {
-                    let error_string = error.to_string();
-                    println!("Seek Error: {}\n", error_string);
-                    usage();
-                    process::exit(exitcode::IOERR);
-                }
+    let folder = args.out_dir.join(&job.folder).join(file_stem(&job.input));
+    for entry in textures {
+        let tex0 = match &entry.file {
+            SubFile::Tex0(tex0) => tex0,
+            _ => continue,
+        };
+
+        let plt0 = palettes.iter().find_map(|p| match &p.file {
+            SubFile::Plt0(plt0) if p.name == entry.name => Some(plt0),
+            _ => None,
+        });
+        let gct = match (plt0, tex0.encoding.has_palette()) {
+            (Some(plt0), true) => Gct::from((tex0, plt0)),
+            (None, true) => {
+                report.warnings.push(format!(
+                    "{} has no palette named \"{}\"",
+                    PALETTES, entry.name
+                ));
+                Gct::from(tex0)
             }
+            (_, false) => Gct::from(tex0),
+        };
 
-            let mut rgb_byte = [0; 1];
-            let rgb_result = plt0_file.read_exact(&mut rgb_byte);
-            match rgb_result {
-                Ok(_) => {}
-                Err(error) => {
-                    let error_string = error.to_string();
-                    println!("Unable to read width: {}\n", error_string);
-                    usage();
-                    process::exit(exitcode::IOERR);
-                }
-            }
-
-            gct_file[0x15] = rgb_byte[0];
-
-            let seek_result = plt0_file.seek(SeekFrom::Start(0x40));
-            match seek_result {
-                Ok(_) => {}
-                Err(error) => {
-                    let error_string = error.to_string();
-                    println!("Seek Error: {}\n", error_string);
-                    usage();
-                    process::exit(exitcode::IOERR);
-                }
-            }
+        for problem in gct.validate() {
+            report.warnings.push(format!("{}: {}", entry.name, problem));
+        }
 
-            let mut rest_of_file = Vec::new();
-            let read_result = plt0_file.read_to_end(&mut rest_of_file);
-            match read_result {
-                Ok(_) => {}
-                Err(error) => {
-                    let error_string = error.to_string();
-                    println!("Unable to read rest of file: {}\n", error_string);
-                    usage();
-                    process::exit(exitcode::IOERR);
-                }
-            }
+        // names can hold characters that aren't allowed in file names
+        let name: String = entry
+            .name
+            .chars()
+            .map(|c| if "/\\:*?\"<>|".contains(c) { '_' } else { c })
+            .collect();
+        let gct_path = folder.join(format!("{}.gct", name));
+        write_output(gct_path, &gct.to_bytes(), report)?;
+    }
+    Ok(())
+}
 
-            rest_of_file.truncate(rest_of_file.len() - footer_len);
-            gct_file.extend(rest_of_file);
+/// Swaps the texture named by the second input in the BRRES `job` is for
+/// with the third input, encoded like the texture it replaces unless
+/// `--format` says otherwise.
+fn replace(args: &Args, job: &Job, report: &mut Report) -> Result<(), GctconvError> {
+    let name = args.inputs[1].to_string_lossy().into_owned();
+    let bytes = fs::read(&job.input)?;
+    match FileKind::detect(&bytes) {
+        Some(FileKind::Brres) => {}
+        Some(found) => {
+            return Err(GctconvError::WrongFormat {
+                found,
+                expected: &[FileKind::Brres],
+            })
         }
-        (_, false) => {}
+        None => return Err(GctconvError::UnknownFormat),
     }
 
-    let gct_path = format!("output/{}.gct", fs_string);
-    let write_result = fs::write(gct_path, gct_file);
-    match write_result {
-        Ok(_) => {}
-        Err(error) => {
-            let error_string = error.to_string();
-            println!("Unable to write GCT file: {}\n", error_string);
-            usage();
-            process::exit(exitcode::IOERR);
+    let brres = Brres::parse(&bytes)?;
+    let find = |folder| {
+        brres
+            .folder(folder)
+            .and_then(|f| f.entries.iter().find(|e| e.name == name))
+            .map(|e| &e.file)
+    };
+    let old = match find(TEXTURES) {
+        Some(SubFile::Tex0(tex0)) => tex0,
+        _ => {
+            return Err(GctconvError::MissingEntry {
+                folder: TEXTURES,
+                name,
+            })
         }
-    }
-}
+    };
+    let old_plt0 = match find(PALETTES) {
+        Some(SubFile::Plt0(plt0)) => Some(plt0),
+        _ => None,
+    };
 
-fn encoding_has_palette(enc_byte: [u8; 1]) -> bool {
-    matches!(
-        FromPrimitive::from_u8(enc_byte[0]),
-        Some(EncodingType::Ci4) | Some(EncodingType::Ci8)
-    )
-}
+    // re-encoded to match the old texture and palette where it can be
+    let encoding = args.format.unwrap_or(old.encoding);
+    let options = EncodeOptions {
+        palette_format: old_plt0.map_or(args.options.palette_format, |p| p.format),
+        ..args.options
+    };
+    let new = fs::read(&args.inputs[2])?;
+    let mut gct = match FileKind::detect(&new) {
+        Some(FileKind::Png) => {
+            let (width, height, rgba) = png_to_rgba(&new)?;
+            Gct::from_rgba(encoding, width, height, &rgba, &options)?
+        }
+        Some(FileKind::Gct) => {
+            let gct = Gct::parse(&new)?;
+            if gct.encoding == encoding {
+                gct
+            } else {
+                // the mip chain is rebuilt in the new encoding too
+                let rgba = gct.to_rgba()?;
+                let mut encoded = Gct::from_rgba(encoding, gct.width, gct.height, &rgba, &options)?;
+                if gct.levels > 1 {
+                    encoded.generate_mipmaps(gct.levels, args.mip_filter, &options)?;
+                }
+                encoded
+            }
+        }
+        Some(found) => {
+            return Err(GctconvError::WrongFormat {
+                found,
+                expected: Command::Replace.inputs(),
+            })
+        }
+        None => return Err(GctconvError::UnknownFormat),
+    };
+    add_mipmaps(args, &mut gct)?;
+    report.texture = Some(Texture::of_gct(&gct));
+    warn_problems(report, gct.validate());
+
+    let expected = (old.width, old.height, old.encoding);
+    let found = (gct.width, gct.height, gct.encoding);
+    if expected != found && !args.force {
+        return Err(GctconvError::IncompatibleTexture {
+            name,
+            expected,
+            found,
+        });
+    }
+
+    let mut tex0 = Tex0::from(&gct);
+    tex0.name = name.clone();
+    tex0.version = old.version;
+    let plt0 = if gct.encoding.has_palette() {
+        let mut plt0 = Plt0::try_from(&gct)?;
+        plt0.name = name.clone();
+        Some(plt0)
+    } else {
+        None
+    };
 
-fn encoding_palette_size_from_byte(enc_byte: [u8; 1]) -> u32 {
-    match FromPrimitive::from_u8(enc_byte[0]) {
-        Some(e) => encoding_palette_size_u32(e),
-        None => 0,
-    }
+    let replaced = Brres::replace_texture(&bytes, &name, &tex0, plt0.as_ref())?;
+    write_output(output_path(args, job, None, "brres"), &replaced, report)
 }
 
-fn encoding_palette_size_u32(enc: EncodingType) -> u32 {
-    match enc {
-        EncodingType::Ci4 => 0x20,
-        EncodingType::Ci8 => 0x200,
-        _ => 0,
-    }
-}
+/// Reports every problem with a GCT, a TEX0 and its PLT0, or each texture
+/// in a BRRES, failing if there are any.
+fn check(job: &Job, bytes: &[u8], kind: FileKind, report: &mut Report) -> Result<(), GctconvError> {
+    let problems: Vec<String> = match kind {
+        FileKind::Gct => {
+            let gct = Gct::parse(bytes)?;
+            report.texture = Some(Texture::of_gct(&gct));
+            gct.validate().iter().map(Problem::to_string).collect()
+        }
+        FileKind::Tex0 => {
+            let tex0 = Tex0::parse(bytes)?;
+            let plt0 = match (&job.palette, tex0.encoding.has_palette()) {
+                (Some(plt0_path), true) => Some(Plt0::parse(&fs::read(plt0_path)?)?),
+                _ => None,
+            };
+            report.texture = Some(Texture::of_tex0(&tex0, plt0.as_ref()));
+            let problems = tex0.validate(plt0.as_ref());
+            problems.iter().map(Problem::to_string).collect()
+        }
+        _ => {
+            let brres = Brres::parse(bytes)?;
+            let entries = |folder| brres.folder(folder).map_or(&[][..], |f| &f.entries[..]);
+            let mut problems = Vec::new();
+            for entry in entries(TEXTURES) {
+                let tex0 = match &entry.file {
+                    SubFile::Tex0(tex0) => tex0,
+                    _ => continue,
+                };
+                let plt0 = entries(PALETTES).iter().find_map(|p| match &p.file {
+                    SubFile::Plt0(plt0) if p.name == entry.name => Some(plt0),
+                    _ => None,
+                });
+                for problem in tex0.validate(plt0) {
+                    problems.push(format!("{}: {}", entry.name, problem));
+                }
+            }
+            problems
+        }
+    };
 
-fn encoding_palette_size_usize(enc: EncodingType) -> usize {
-    match enc {
-        EncodingType::Ci4 => 0x20,
-        EncodingType::Ci8 => 0x200,
-        _ => 0,
+    let count = problems.len();
+    report.warnings.extend(problems);
+    if count > 0 {
+        return Err(GctconvError::Invalid(count));
     }
+    Ok(())
 }
 
-fn read_short(mut file: &File) -> Result<u16, Error> {
-    let mut buffer = [0; 2]; // 2 byte buffer
-    file.read_exact(&mut buffer)?;
-
-    let s = u16::from_be_bytes(buffer);
-    Ok(s)
+fn info(bytes: &[u8], report: &mut Report) -> Result<(), GctconvError> {
+    report.info = Some(FileInfo::read(bytes)?);
+    Ok(())
 }
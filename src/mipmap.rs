@@ -0,0 +1,59 @@
+//! Mip chains: smaller and smaller copies of a texture, each half the size
+//! of the one before down to 1x1, stored one after the other after the base
+//! level in the same encoding.
+
+use crate::{EncodingType, GctconvError};
+
+/// The most levels a `width` by `height` texture can have, counting the base
+/// level, with the last one 1x1.
+pub fn max_levels(width: u16, height: u16) -> u32 {
+    let largest = width.max(height).max(1);
+    u16::BITS - largest.leading_zeros()
+}
+
+/// The size of level `level` of a `width` by `height` texture, level 0 being
+/// the texture itself.
+pub fn level_size(width: u16, height: u16, level: u32) -> (u16, u16) {
+    let halve = |size: u16| size.checked_shr(level).unwrap_or(0).max(1);
+    (halve(width), halve(height))
+}
+
+/// The number of levels in `size` bytes of image data, for GCTs, which have
+/// no field for it: a power of two texture whose data is exactly the size
+/// of a longer mip chain has that chain, and any other has just itself.
+pub(crate) fn levels_in_size(encoding: EncodingType, width: u16, height: u16, size: usize) -> u32 {
+    if !(width.is_power_of_two() && height.is_power_of_two()) {
+        return 1;
+    }
+    (2..=max_levels(width, height))
+        .find(|&levels| encoding.chain_size(width, height, levels) == size)
+        .unwrap_or(1)
+}
+
+/// Checks the image count stored at `offset` against the texture: there
+/// can't be levels past 1x1, and a mip chain has to be all there.
+pub(crate) fn check_levels(
+    encoding: EncodingType,
+    width: u16,
+    height: u16,
+    levels: u32,
+    offset: usize,
+    data: &[u8],
+) -> Result<(), GctconvError> {
+    if levels > max_levels(width, height) {
+        return Err(GctconvError::BadHeaderValue {
+            offset,
+            value: levels as usize,
+        });
+    }
+
+    // a lone texture short on data still fails when it is decoded
+    let needed = encoding.chain_size(width, height, levels);
+    if levels > 1 && data.len() < needed {
+        return Err(GctconvError::DataTooShort {
+            needed,
+            found: data.len(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,313 @@
+//! Palette quantization for the colour index encodings.
+
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{decode::palette_colour, encode::palette_entry, PaletteFormat};
+
+/// Rounds of k-means refinement run on the median cut palette.
+const REFINE_ROUNDS: usize = 4;
+
+/// Palettes larger than this (CI14x2's) skip k-means, as matching every
+/// colour against every entry gets too slow.
+const MAX_REFINED_COLOURS: usize = 0x100;
+
+/// Reduces an RGBA8 image to at most `colours` palette entries in `format`,
+/// returning the palette and the palette index of every pixel.
+///
+/// Colours are first snapped to what `format` can store, then split into
+/// boxes by median cut, and finally refined with a few rounds of k-means.
+pub(crate) fn build_palette(
+    rgba: &[u8],
+    colours: usize,
+    format: PaletteFormat,
+) -> (Vec<u16>, Vec<u16>) {
+    let mut histogram: HashMap<u16, u32> = HashMap::new();
+    for pixel in rgba.chunks_exact(4) {
+        let entry = palette_entry(format, [pixel[0], pixel[1], pixel[2], pixel[3]]);
+        *histogram.entry(entry).or_insert(0) += 1;
+    }
+
+    let mut unique: Vec<(u16, u32)> = histogram.into_iter().collect();
+    unique.sort_unstable();
+
+    // every palette entry, with the colours that map to it
+    let (palette, clusters) = if unique.len() <= colours {
+        let palette = unique.iter().map(|(entry, _)| *entry).collect();
+        let clusters = unique.iter().map(|&u| vec![u]).collect();
+        (palette, clusters)
+    } else {
+        let mut clusters = median_cut(&unique, colours, format);
+        let mut palette = means(&clusters, &vec![0; clusters.len()], format);
+        if colours <= MAX_REFINED_COLOURS {
+            for _ in 0..REFINE_ROUNDS {
+                clusters = assign(&unique, &palette, format);
+                palette = means(&clusters, &palette, format);
+            }
+            clusters = assign(&unique, &palette, format);
+        }
+        (palette, clusters)
+    };
+
+    let mut lookup: HashMap<u16, u16> = HashMap::new();
+    for (index, cluster) in clusters.iter().enumerate() {
+        for &(entry, _) in cluster {
+            lookup.insert(entry, index as u16);
+        }
+    }
+
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|pixel| lookup[&palette_entry(format, [pixel[0], pixel[1], pixel[2], pixel[3]])])
+        .collect();
+
+    (palette, indices)
+}
+
+/// Finds the nearest entry of a palette of decoded colours to colour after
+/// colour, remembering every answer.
+///
+/// The entries are kept in a k-d tree, so CI14x2's 16384 entries aren't all
+/// compared against every colour. Ties go to the lowest index, as with
+/// `nearest`.
+pub(crate) struct PaletteSearch<'a> {
+    palette: &'a [[u8; 4]],
+    /// Palette indices laid out as a balanced tree: the middle of any range
+    /// splits the rest of it on `channels[middle]`, lower values first.
+    tree: Vec<usize>,
+    channels: Vec<usize>,
+    found: HashMap<[u8; 4], usize>,
+}
+
+impl<'a> PaletteSearch<'a> {
+    pub(crate) fn new(palette: &'a [[u8; 4]]) -> PaletteSearch<'a> {
+        let mut search = PaletteSearch {
+            palette,
+            tree: (0..palette.len()).collect(),
+            channels: vec![0; palette.len()],
+            found: HashMap::new(),
+        };
+        search.build(0, palette.len());
+        search
+    }
+
+    /// Splits `start..end` of the tree on the channel its entries are most
+    /// spread out in, then each half in turn.
+    fn build(&mut self, start: usize, end: usize) {
+        if end - start < 2 {
+            return;
+        }
+        let palette = self.palette;
+        let range = &mut self.tree[start..end];
+        let channel = (0..4)
+            .max_by_key(|&ch| {
+                let values = range.iter().map(|&i| palette[i][ch]);
+                values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+            })
+            .unwrap_or(0);
+        let middle = (end - start) / 2;
+        range.select_nth_unstable_by_key(middle, |&i| palette[i][channel]);
+        self.channels[start + middle] = channel;
+
+        self.build(start, start + middle);
+        self.build(start + middle + 1, end);
+    }
+
+    pub(crate) fn nearest(&mut self, colour: [u8; 4]) -> usize {
+        if let Some(&index) = self.found.get(&colour) {
+            return index;
+        }
+        let mut best = (0, u32::MAX);
+        self.search(0, self.tree.len(), colour, &mut best);
+        self.found.insert(colour, best.0);
+        best.0
+    }
+
+    fn search(&self, start: usize, end: usize, colour: [u8; 4], best: &mut (usize, u32)) {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let index = self.tree[middle];
+        let entry = self.palette[index];
+        let error = distance(entry, colour);
+        if error < best.1 || (error == best.1 && index < best.0) {
+            *best = (index, error);
+        }
+
+        let channel = self.channels[middle];
+        let gap = colour[channel] as i32 - entry[channel] as i32;
+        let (near, far) = if gap < 0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        self.search(near.0, near.1, colour, best);
+        // entries past the split are at least `gap` away on its channel;
+        // one just as near as the best could still have a lower index
+        if (gap * gap) as u32 <= best.1 {
+            self.search(far.0, far.1, colour, best);
+        }
+    }
+}
+
+pub(crate) fn nearest(palette: &[[u8; 4]], colour: [u8; 4]) -> usize {
+    let mut best = (0, u32::MAX);
+    for (i, entry) in palette.iter().enumerate() {
+        let error = distance(*entry, colour);
+        if error < best.1 {
+            best = (i, error);
+        }
+    }
+    best.0
+}
+
+pub(crate) fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..4)
+        .map(|ch| {
+            let d = a[ch] as i32 - b[ch] as i32;
+            (d * d) as u32
+        })
+        .sum()
+}
+
+/// Weighted mean of the colours in each cluster, snapped to `format`.
+/// Empty clusters keep their entry from `palette`.
+fn means(clusters: &[Vec<(u16, u32)>], palette: &[u16], format: PaletteFormat) -> Vec<u16> {
+    clusters
+        .iter()
+        .zip(palette)
+        .map(|(cluster, &entry)| {
+            if cluster.is_empty() {
+                return entry;
+            }
+
+            let mut sums = [0_u64; 4];
+            let mut total = 0_u64;
+            for &(member, count) in cluster {
+                let colour = palette_colour(format, member);
+                for ch in 0..4 {
+                    sums[ch] += colour[ch] as u64 * count as u64;
+                }
+                total += count as u64;
+            }
+
+            let mut mean = [0; 4];
+            for ch in 0..4 {
+                mean[ch] = ((sums[ch] + total / 2) / total) as u8;
+            }
+            palette_entry(format, mean)
+        })
+        .collect()
+}
+
+/// Splits the colours into `colours` boxes, returning the colours in each.
+fn median_cut(
+    unique: &[(u16, u32)],
+    colours: usize,
+    format: PaletteFormat,
+) -> Vec<Vec<(u16, u32)>> {
+    // boxes by the range of their widest channel, so the widest is split next
+    let mut boxes = BinaryHeap::new();
+    let (channel, range) = widest_channel(unique, format);
+    boxes.push((range, channel, unique.to_vec()));
+
+    while boxes.len() < colours {
+        let (range, channel, mut to_split) = match boxes.pop() {
+            Some(b) => b,
+            None => break,
+        };
+        if range == 0 {
+            // only single colours are left
+            boxes.push((range, channel, to_split));
+            break;
+        }
+
+        to_split.sort_by_key(|&(entry, _)| palette_colour(format, entry)[channel]);
+
+        // split at the weighted median, keeping both halves non-empty
+        let total: u64 = to_split.iter().map(|(_, count)| *count as u64).sum();
+        let mut running = 0;
+        let mut split = 1;
+        for (i, (_, count)) in to_split.iter().enumerate() {
+            running += *count as u64;
+            if running * 2 >= total {
+                split = (i + 1).clamp(1, to_split.len() - 1);
+                break;
+            }
+        }
+
+        let upper = to_split.split_off(split);
+        for half in [to_split, upper] {
+            let (channel, range) = widest_channel(&half, format);
+            boxes.push((range, channel, half));
+        }
+    }
+
+    boxes.into_iter().map(|(_, _, b)| b).collect()
+}
+
+/// The channel with the largest range in `colours`, and that range.
+fn widest_channel(colours: &[(u16, u32)], format: PaletteFormat) -> (usize, u8) {
+    let mut min = [u8::MAX; 4];
+    let mut max = [0; 4];
+    for &(entry, _) in colours {
+        let colour = palette_colour(format, entry);
+        for ch in 0..4 {
+            min[ch] = min[ch].min(colour[ch]);
+            max[ch] = max[ch].max(colour[ch]);
+        }
+    }
+
+    let mut best = (0, 0);
+    for ch in 0..4 {
+        if max[ch].saturating_sub(min[ch]) > best.1 {
+            best = (ch, max[ch] - min[ch]);
+        }
+    }
+    best
+}
+
+/// Groups every colour with the palette entry nearest to it.
+fn assign(unique: &[(u16, u32)], palette: &[u16], format: PaletteFormat) -> Vec<Vec<(u16, u32)>> {
+    let decoded: Vec<[u8; 4]> = palette
+        .iter()
+        .map(|&entry| palette_colour(format, entry))
+        .collect();
+
+    let mut clusters = vec![Vec::new(); palette.len()];
+    for &(entry, count) in unique {
+        clusters[nearest(&decoded, palette_colour(format, entry))].push((entry, count));
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repeatable stream of made-up colours.
+    fn colours(seed: u32) -> impl Iterator<Item = [u8; 4]> {
+        let mut state = seed;
+        std::iter::repeat_with(move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            state.to_be_bytes()
+        })
+    }
+
+    #[test]
+    fn search_matches_brute_force() {
+        let mut palette: Vec<[u8; 4]> = colours(1).take(300).collect();
+        // repeated entries, which must go to the first of them
+        palette.extend_from_within(10..40);
+
+        let mut search = PaletteSearch::new(&palette);
+        for colour in colours(2).take(2000).chain(palette.iter().copied()) {
+            assert_eq!(
+                search.nearest(colour),
+                nearest(&palette, colour),
+                "{:?}",
+                colour
+            );
+        }
+    }
+}
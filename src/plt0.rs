@@ -0,0 +1,126 @@
+use std::convert::TryFrom;
+
+use crate::{
+    check_magic_at, name_block, read_data_and_name, read_u8, Gct, GctconvError, PaletteFormat,
+    HEADER_SIZE,
+};
+
+/// An NW4R palette sub-file (`PLT0`), holding the palette for a CI4, CI8 or
+/// CI14x2 `Tex0` of the same name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plt0 {
+    pub name: String,
+    pub format: PaletteFormat,
+    pub data: Vec<u8>,
+}
+
+impl Plt0 {
+    pub fn parse(bytes: &[u8]) -> Result<Plt0, GctconvError> {
+        Plt0::parse_at(bytes, 0)
+    }
+
+    /// Parses the PLT0 starting at `base`, such as one inside a BRRES.
+    pub(crate) fn parse_at(bytes: &[u8], base: usize) -> Result<Plt0, GctconvError> {
+        check_magic_at(bytes, base, b"PLT0")?;
+
+        let rgb_byte = read_u8(bytes, base + 0x1B)?;
+        let format = match PaletteFormat::from_byte(rgb_byte) {
+            Some(f) => f,
+            None => return Err(GctconvError::UnsupportedPaletteFormat(rgb_byte)),
+        };
+
+        let (data, name) = read_data_and_name(bytes, base, 0x14)?;
+        let data = data.to_vec();
+
+        Ok(Plt0 { name, format, data })
+    }
+
+    /// Number of two byte colours in the palette.
+    pub fn entry_count(&self) -> usize {
+        self.data.len() / 2
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GctconvError> {
+        let mut plt0_file = self.header_and_data();
+        plt0_file.extend(name_block(&self.name)?);
+        Ok(plt0_file)
+    }
+
+    /// The PLT0 without the name that trails it, which is in the string
+    /// table instead inside a BRRES.
+    pub(crate) fn header_and_data(&self) -> Vec<u8> {
+        let size = (HEADER_SIZE + self.data.len()) as u32;
+
+        // magic, size, version 1, no parent BRRES, data offset, name offset
+        let mut header = b"PLT0".to_vec();
+        header.extend(&size.to_be_bytes());
+        header.extend(&1_u32.to_be_bytes());
+        header.extend(&0_u32.to_be_bytes());
+        header.extend(&(HEADER_SIZE as u32).to_be_bytes());
+        header.extend(&(size + 0x4).to_be_bytes());
+        // header == [...[0x18]]
+
+        // palette format as int, number of entries as short
+        header.extend(&(self.format as u32).to_be_bytes());
+        header.extend(&(self.entry_count() as u16).to_be_bytes());
+        // header == [...[0x1E]]
+
+        header.resize(HEADER_SIZE, 0);
+
+        let mut plt0_file = header;
+        plt0_file.extend(&self.data);
+        plt0_file
+    }
+}
+
+impl TryFrom<&Gct> for Plt0 {
+    type Error = GctconvError;
+
+    /// Takes the palette of a colour index GCT, leaving the name empty.
+    fn try_from(gct: &Gct) -> Result<Plt0, GctconvError> {
+        if !gct.encoding.has_palette() {
+            return Err(GctconvError::NoPalette);
+        }
+
+        Ok(Plt0 {
+            name: String::new(),
+            format: gct.palette_format,
+            data: gct.palette_data.clone(),
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for Plt0 {
+    type Error = GctconvError;
+
+    fn try_from(bytes: &[u8]) -> Result<Plt0, GctconvError> {
+        Plt0::parse(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for (format, entries) in [
+            (PaletteFormat::Ia8, 16),
+            (PaletteFormat::Rgb565, 3),
+            (PaletteFormat::Rgb5A3, 256),
+        ] {
+            let plt0 = Plt0 {
+                name: "palette".to_owned(),
+                format,
+                data: (0..entries * 2).map(|i| i as u8).collect(),
+            };
+            let bytes = plt0.to_bytes().unwrap();
+            assert_eq!(bytes[0x1B], format as u8);
+            assert_eq!(
+                u16::from_be_bytes([bytes[0x1C], bytes[0x1D]]) as usize,
+                entries
+            );
+            assert_eq!(Plt0::parse(&bytes).unwrap(), plt0);
+        }
+    }
+}
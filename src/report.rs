@@ -0,0 +1,362 @@
+//! What converting each file did, printed for people or as JSON for build
+//! scripts.
+
+use std::path::{Path, PathBuf};
+
+use gctconv::{
+    EncodingType, FieldValue, FileInfo, FileKind, Gct, GctconvError, PaletteFormat, Plt0, Tex0,
+};
+
+use crate::{batch::Job, cli::Command, json::Json};
+
+/// The texture a conversion read.
+pub struct Texture {
+    pub width: u16,
+    pub height: u16,
+    pub encoding: EncodingType,
+    pub palette_format: Option<PaletteFormat>,
+    pub levels: u32,
+}
+
+impl Texture {
+    pub fn of_gct(gct: &Gct) -> Texture {
+        Texture {
+            width: gct.width,
+            height: gct.height,
+            encoding: gct.encoding,
+            palette_format: Some(gct.palette_format).filter(|_| gct.encoding.has_palette()),
+            levels: gct.levels,
+        }
+    }
+
+    pub fn of_tex0(tex0: &Tex0, plt0: Option<&Plt0>) -> Texture {
+        Texture {
+            width: tex0.width,
+            height: tex0.height,
+            encoding: tex0.encoding,
+            palette_format: plt0.map(|p| p.format),
+            levels: tex0.levels,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("width", self.width.into()),
+            ("height", self.height.into()),
+            ("encoding", self.encoding.name().into()),
+            (
+                "palette_format",
+                self.palette_format.map(|f| f.name()).into(),
+            ),
+            ("levels", self.levels.into()),
+        ])
+    }
+}
+
+/// Everything a job has to report, besides whether it failed.
+#[derive(Default)]
+pub struct Report {
+    /// The conversion that was picked for the file.
+    pub command: Option<Command>,
+    pub warnings: Vec<String>,
+    /// Every file written, in the order they were written.
+    pub outputs: Vec<PathBuf>,
+    pub texture: Option<Texture>,
+    /// The description of the file, for `info`.
+    pub info: Option<FileInfo>,
+    /// The texture and palette `to-brres` packs, once every file is
+    /// converted.
+    pub packed: Option<(Tex0, Option<Plt0>)>,
+}
+
+impl Report {
+    /// The lines to show the user.
+    pub fn lines(&self, job: &Job) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(info) = &self.info {
+            describe(info, job, &mut lines);
+        }
+        for warning in &self.warnings {
+            lines.push(format!("{}: {}", job.input.display(), warning));
+        }
+        lines
+    }
+
+    /// A JSON object with the same keys for every job, whatever the
+    /// command, with `null` for whatever doesn't apply.
+    pub fn to_json(&self, command: Command, job: &Job, result: &Result<(), GctconvError>) -> Json {
+        let input = json_path(&job.input);
+        let palette = job.palette.as_deref().map_or(Json::Null, json_path);
+        self.json(command, input, palette, result)
+    }
+
+    /// The JSON object for the archive `to-brres` packs everything into,
+    /// after the ones for its inputs. It has no input of its own.
+    pub fn archive_json(&self, result: &Result<(), GctconvError>) -> Json {
+        self.json(Command::ToBrres, Json::Null, Json::Null, result)
+    }
+
+    fn json(
+        &self,
+        command: Command,
+        input: Json,
+        palette: Json,
+        result: &Result<(), GctconvError>,
+    ) -> Json {
+        Json::Object(vec![
+            ("input", input),
+            ("palette", palette),
+            ("command", command.name().into()),
+            ("ok", result.is_ok().into()),
+            ("error", result.as_ref().err().map(|e| e.to_string()).into()),
+            ("warnings", self.warnings.clone().into()),
+            (
+                "outputs",
+                Json::Array(self.outputs.iter().map(|p| json_path(p)).collect()),
+            ),
+            (
+                "texture",
+                self.texture.as_ref().map_or(Json::Null, Texture::to_json),
+            ),
+            ("info", self.info.as_ref().map_or(Json::Null, info_json)),
+        ])
+    }
+}
+
+fn json_path(path: &Path) -> Json {
+    Json::from(path.to_string_lossy().into_owned())
+}
+
+/// Lists every header field, then what they add up to.
+fn describe(info: &FileInfo, job: &Job, lines: &mut Vec<String>) {
+    lines.push(format!("{}: {}", job.input.display(), info.kind.name()));
+
+    for field in &info.fields {
+        let mut line = format!(
+            "    {:#06x}  {:<18}{}",
+            field.offset, field.name, field.value
+        );
+        if let Some(meaning) = field.meaning {
+            line += &format!(" ({})", meaning);
+        }
+        lines.push(line);
+    }
+
+    let mut derived = Vec::new();
+    if let Some(name) = &info.name {
+        derived.push(("name", format!("{:?}", name)));
+    }
+    if let Some(entries) = info.palette_entries {
+        derived.push(("palette entries", entries.to_string()));
+    }
+    // GCTs have no field for it, so it comes from the data size
+    if let (FileKind::Gct, Some(levels)) = (info.kind, info.levels) {
+        derived.push(("image count", levels.to_string()));
+    }
+    derived.push(("data size", format!("{:#x}", info.data_size)));
+    if let Some(expected) = info.expected_data_size {
+        derived.push(("expected data", format!("{:#x}", expected)));
+    }
+    derived.push(("file size", format!("{:#x}", info.file_size)));
+    for (name, value) in derived {
+        lines.push(format!("            {:<18}{}", name, value));
+    }
+
+    for mismatch in info.mismatches() {
+        lines.push(format!("    mismatch: {}", mismatch));
+    }
+}
+
+fn info_json(info: &FileInfo) -> Json {
+    let fields = info
+        .fields
+        .iter()
+        .map(|field| {
+            let value = match &field.value {
+                FieldValue::Int(v) | FieldValue::Offset(v) => Json::Int(*v),
+                FieldValue::Float(v) => (*v).into(),
+                FieldValue::Text(v) => v.as_str().into(),
+            };
+            Json::Object(vec![
+                ("offset", field.offset.into()),
+                ("name", field.name.into()),
+                ("value", value),
+                ("meaning", field.meaning.into()),
+            ])
+        })
+        .collect();
+
+    Json::Object(vec![
+        ("kind", info.kind.name().to_ascii_lowercase().into()),
+        ("name", info.name.clone().into()),
+        ("width", info.width.into()),
+        ("height", info.height.into()),
+        ("encoding", info.encoding.map(|e| e.name()).into()),
+        (
+            "palette_format",
+            info.palette_format.map(|f| f.name()).into(),
+        ),
+        ("palette_entries", info.palette_entries.into()),
+        ("levels", info.levels.into()),
+        ("data_size", info.data_size.into()),
+        ("expected_data_size", info.expected_data_size.into()),
+        ("declared_size", info.declared_size.into()),
+        ("file_size", info.file_size.into()),
+        ("mismatches", info.mismatches().into()),
+        ("fields", Json::Array(fields)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use gctconv::EncodeOptions;
+
+    use super::*;
+
+    const JOB_KEYS: [&str; 9] = [
+        "input", "palette", "command", "ok", "error", "warnings", "outputs", "texture", "info",
+    ];
+    const TEXTURE_KEYS: [&str; 5] = ["width", "height", "encoding", "palette_format", "levels"];
+    const INFO_KEYS: [&str; 14] = [
+        "kind",
+        "name",
+        "width",
+        "height",
+        "encoding",
+        "palette_format",
+        "palette_entries",
+        "levels",
+        "data_size",
+        "expected_data_size",
+        "declared_size",
+        "file_size",
+        "mismatches",
+        "fields",
+    ];
+    const FIELD_KEYS: [&str; 4] = ["offset", "name", "value", "meaning"];
+
+    fn keys(json: &Json) -> Vec<&'static str> {
+        match json {
+            Json::Object(members) => members.iter().map(|(key, _)| *key).collect(),
+            _ => panic!("not an object"),
+        }
+    }
+
+    fn member<'a>(json: &'a Json, name: &str) -> &'a Json {
+        match json {
+            Json::Object(members) => &members.iter().find(|(key, _)| *key == name).unwrap().1,
+            _ => panic!("not an object"),
+        }
+    }
+
+    fn job() -> Job {
+        Job {
+            input: PathBuf::from("in.gct"),
+            palette: None,
+            folder: PathBuf::new(),
+        }
+    }
+
+    fn gct() -> Gct {
+        let options = EncodeOptions::default();
+        Gct::from_rgba(EncodingType::Ci4, 8, 8, &[0; 8 * 8 * 4], &options).unwrap()
+    }
+
+    /// The name of every header field in the info JSON of `bytes`.
+    fn field_names(bytes: &[u8]) -> Vec<String> {
+        let report = Report {
+            info: Some(FileInfo::read(bytes).unwrap()),
+            ..Report::default()
+        };
+        let json = report.to_json(Command::Info, &job(), &Ok(()));
+        assert_eq!(keys(&json), JOB_KEYS);
+        let info = member(&json, "info");
+        assert_eq!(keys(info), INFO_KEYS);
+
+        let fields = match member(info, "fields") {
+            Json::Array(fields) => fields,
+            _ => panic!("fields aren't an array"),
+        };
+        fields
+            .iter()
+            .map(|field| {
+                assert_eq!(keys(field), FIELD_KEYS);
+                match member(field, "name") {
+                    Json::Str(name) => name.clone(),
+                    _ => panic!("name isn't a string"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn conversion_keys() {
+        let gct = gct();
+        let report = Report {
+            texture: Some(Texture::of_gct(&gct)),
+            outputs: vec![PathBuf::from("out.tex0")],
+            ..Report::default()
+        };
+        for result in [Ok(()), Err(GctconvError::MissingPalette)] {
+            let json = report.to_json(Command::ToTex0, &job(), &result);
+            assert_eq!(keys(&json), JOB_KEYS);
+            assert_eq!(keys(member(&json, "texture")), TEXTURE_KEYS);
+            assert_eq!(keys(&report.archive_json(&result)), JOB_KEYS);
+        }
+    }
+
+    #[test]
+    fn info_keys_for_each_kind() {
+        let gct = gct();
+        let mut tex0 = Tex0::from(&gct);
+        tex0.name = "texture".to_owned();
+
+        assert_eq!(
+            field_names(&gct.to_bytes()),
+            [
+                "magic",
+                "version",
+                "header size",
+                "image data size",
+                "width",
+                "height",
+                "encoding",
+                "palette format",
+                "signature",
+            ]
+        );
+        assert_eq!(
+            field_names(&tex0.to_bytes().unwrap()),
+            [
+                "magic",
+                "size",
+                "version",
+                "BRRES offset",
+                "data offset",
+                "name offset",
+                "has palette",
+                "width",
+                "height",
+                "encoding",
+                "image count",
+                "min LOD",
+                "max LOD",
+            ]
+        );
+        assert_eq!(
+            field_names(&Plt0::try_from(&gct).unwrap().to_bytes().unwrap()),
+            [
+                "magic",
+                "size",
+                "version",
+                "BRRES offset",
+                "data offset",
+                "name offset",
+                "palette format",
+                "entry count",
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Resampling of RGBA8 images to other sizes, for mip levels.
+//!
+//! Images are filtered separably, first along rows then along columns, with
+//! colour premultiplied by alpha so transparent pixels don't bleed their
+//! colour into their neighbours.
+
+use std::f64::consts::PI;
+
+use crate::GctconvError;
+
+/// The filters an image can be resampled with, from quickest and softest to
+/// sharpest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// The average of the pixels each new pixel covers.
+    #[default]
+    Box,
+    /// A linear falloff over twice the width of a new pixel.
+    Triangle,
+    /// A three lobed windowed sinc, sharp but prone to slight ringing.
+    Lanczos,
+    /// A sinc in a Kaiser window, a little softer than Lanczos with less
+    /// ringing.
+    Kaiser,
+}
+
+const FILTER_NAMES: [(&str, Filter); 4] = [
+    ("box", Filter::Box),
+    ("triangle", Filter::Triangle),
+    ("lanczos", Filter::Lanczos),
+    ("kaiser", Filter::Kaiser),
+];
+
+/// Shape of the Kaiser window; higher is smoother.
+const KAISER_ALPHA: f64 = 4.0;
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        FILTER_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, f)| *f)
+    }
+
+    pub fn name(self) -> &'static str {
+        match FILTER_NAMES.iter().find(|(_, f)| *f == self) {
+            Some((n, _)) => n,
+            None => unreachable!(),
+        }
+    }
+
+    /// How far from its centre the filter reaches, in pixels of the smaller
+    /// of the two images.
+    fn support(self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Triangle => 1.0,
+            Filter::Lanczos | Filter::Kaiser => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let support = self.support();
+        match self {
+            Filter::Box if (-0.5..0.5).contains(&x) => 1.0,
+            Filter::Triangle => (1.0 - x.abs()).max(0.0),
+            Filter::Lanczos if x.abs() < support => sinc(x) * sinc(x / support),
+            Filter::Kaiser if x.abs() < support => {
+                let t = x / support;
+                sinc(x) * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The zeroth order modified Bessel function of the first kind, summed as a
+/// power series until the terms stop mattering.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (half / k) * (half / k);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// The source pixels each destination pixel is made of along one axis: the
+/// first one's index and the weight of each, adding up to 1.
+fn weights(from: usize, to: usize, filter: Filter) -> Vec<(usize, Vec<f32>)> {
+    let scale = from as f64 / to as f64;
+    // shrinking widens the filter to cover every source pixel
+    let stretch = scale.max(1.0);
+    let support = filter.support() * stretch;
+
+    (0..to)
+        .map(|i| {
+            let centre = (i as f64 + 0.5) * scale;
+            let first = (centre - support).floor().max(0.0) as usize;
+            let last = ((centre + support).ceil() as usize).min(from);
+            let mut weights: Vec<f64> = (first..last)
+                .map(|j| filter.weight((j as f64 + 0.5 - centre) / stretch))
+                .collect();
+
+            let total: f64 = weights.iter().sum();
+            if total.abs() < 1e-9 {
+                // too narrow to reach a pixel centre, so take the nearest
+                let nearest = (centre as usize).min(from - 1);
+                return (nearest, vec![1.0]);
+            }
+            for weight in &mut weights {
+                *weight /= total;
+            }
+            (first, weights.into_iter().map(|w| w as f32).collect())
+        })
+        .collect()
+}
+
+/// Resamples a `width` by `height` RGBA8 image, four bytes per pixel, to
+/// `new_width` by `new_height` with `filter`.
+pub fn resize(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+    filter: Filter,
+) -> Result<Vec<u8>, GctconvError> {
+    let needed = width * height * 4;
+    if rgba.len() < needed {
+        return Err(GctconvError::DataTooShort {
+            needed,
+            found: rgba.len(),
+        });
+    }
+    if width == 0 || height == 0 || new_width == 0 || new_height == 0 {
+        return Ok(vec![0; new_width * new_height * 4]);
+    }
+
+    let premultiplied: Vec<[f32; 4]> = rgba
+        .chunks_exact(4)
+        .take(width * height)
+        .map(|p| {
+            let alpha = p[3] as f32 / 255.0;
+            [
+                p[0] as f32 * alpha,
+                p[1] as f32 * alpha,
+                p[2] as f32 * alpha,
+                p[3] as f32,
+            ]
+        })
+        .collect();
+
+    // along each row, then down each column of the result
+    let across = weights(width, new_width, filter);
+    let mut rows = vec![[0.0; 4]; new_width * height];
+    for y in 0..height {
+        for (x, (first, weights)) in across.iter().enumerate() {
+            let mut sum = [0.0; 4];
+            for (i, weight) in weights.iter().enumerate() {
+                let pixel = premultiplied[y * width + first + i];
+                for ch in 0..4 {
+                    sum[ch] += pixel[ch] * weight;
+                }
+            }
+            rows[y * new_width + x] = sum;
+        }
+    }
+
+    let down = weights(height, new_height, filter);
+    let mut resized = vec![0; new_width * new_height * 4];
+    for (y, (first, weights)) in down.iter().enumerate() {
+        for x in 0..new_width {
+            let mut sum = [0.0; 4];
+            for (i, weight) in weights.iter().enumerate() {
+                let pixel = rows[(first + i) * new_width + x];
+                for ch in 0..4 {
+                    sum[ch] += pixel[ch] * weight;
+                }
+            }
+
+            let alpha = sum[3].clamp(0.0, 255.0);
+            let out = &mut resized[(y * new_width + x) * 4..][..4];
+            for ch in 0..3 {
+                let colour = if alpha > 0.0 {
+                    sum[ch] * 255.0 / alpha
+                } else {
+                    0.0
+                };
+                out[ch] = colour.round().clamp(0.0, 255.0) as u8;
+            }
+            out[3] = alpha.round() as u8;
+        }
+    }
+    Ok(resized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Box,
+        Filter::Triangle,
+        Filter::Lanczos,
+        Filter::Kaiser,
+    ];
+
+    #[test]
+    fn solid_colours_stay_solid() {
+        let colour = [40, 120, 200, 255];
+        for filter in FILTERS {
+            for (from, to) in [((5, 3), (13, 7)), ((16, 16), (3, 5)), ((8, 8), (4, 4))] {
+                let rgba = colour.repeat(from.0 * from.1);
+                let resized = resize(&rgba, from.0, from.1, to.0, to.1, filter).unwrap();
+                assert_eq!(resized, colour.repeat(to.0 * to.1), "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn box_halving_averages_each_square() {
+        #[rustfmt::skip]
+        let rgba = [
+            0, 0, 0, 255,      100, 40, 8, 255,    10, 10, 10, 255,  10, 10, 10, 255,
+            200, 80, 16, 255,  100, 40, 8, 255,    30, 30, 30, 255,  30, 30, 30, 255,
+        ];
+        let resized = resize(&rgba, 4, 2, 2, 1, Filter::Box).unwrap();
+        assert_eq!(resized, [100, 40, 8, 255, 20, 20, 20, 255]);
+    }
+
+    #[test]
+    fn transparent_pixels_add_no_colour() {
+        let rgba = [255, 0, 0, 255, 0, 255, 0, 0];
+        let resized = resize(&rgba, 2, 1, 1, 1, Filter::Box).unwrap();
+        assert_eq!(resized, [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn short_buffers_are_an_error() {
+        let resized = resize(&[0; 12], 2, 2, 1, 1, Filter::Box);
+        assert!(matches!(
+            resized,
+            Err(GctconvError::DataTooShort {
+                needed: 16,
+                found: 12
+            })
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+use std::convert::TryFrom;
+
+use crate::{
+    check_magic_at, decode, mipmap::check_levels, name_block, read_data_and_name, read_u16,
+    read_u32, read_u8, validate::check_image, EncodingType, FileKind, Gct, GctconvError, Plt0,
+    Problem, HEADER_SIZE,
+};
+
+/// An NW4R texture sub-file (`TEX0`), as exported by BrawlBox.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tex0 {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    pub encoding: EncodingType,
+    /// The image data of every mip level, one after the other.
+    pub data: Vec<u8>,
+    /// The number of images in the mip chain, 1 for just the texture.
+    pub levels: u32,
+    /// The TEX0 version, one of `Tex0::VERSIONS`.
+    pub version: u32,
+}
+
+/// Version 2 TEX0s have a second section offset, for user data, between the
+/// data offset and the name offset; every field after it is 4 bytes later.
+pub(crate) fn name_offset_field(version: u32) -> usize {
+    if version == 2 {
+        0x18
+    } else {
+        0x14
+    }
+}
+
+/// The header the image data follows. The extra section offset of version 2
+/// pushes it past 0x40, so it is padded on to 0x60 to keep the image data 32
+/// byte aligned.
+fn header_size(version: u32) -> usize {
+    if version == 2 {
+        0x60
+    } else {
+        HEADER_SIZE
+    }
+}
+
+impl Tex0 {
+    /// The TEX0 versions that can be read and written.
+    pub const VERSIONS: [u32; 3] = [1, 2, 3];
+
+    pub fn parse(bytes: &[u8]) -> Result<Tex0, GctconvError> {
+        Tex0::parse_at(bytes, 0)
+    }
+
+    /// Parses the TEX0 starting at `base`, such as one inside a BRRES.
+    pub(crate) fn parse_at(bytes: &[u8], base: usize) -> Result<Tex0, GctconvError> {
+        check_magic_at(bytes, base, b"TEX0")?;
+
+        let version = read_u32(bytes, base + 0x08)?;
+        if !Tex0::VERSIONS.contains(&version) {
+            return Err(GctconvError::UnsupportedVersion {
+                kind: FileKind::Tex0,
+                version,
+            });
+        }
+        let fields = base + name_offset_field(version) + 4;
+
+        let width = read_u16(bytes, fields + 0x04)?;
+        let height = read_u16(bytes, fields + 0x06)?;
+        let enc_byte = read_u8(bytes, fields + 0x0B)?;
+        let encoding = match EncodingType::from_byte(enc_byte) {
+            Some(e) => e,
+            None => return Err(GctconvError::UnsupportedEncoding(enc_byte)),
+        };
+
+        // the texture itself counts, but some tools write 0 for no mipmaps
+        let levels = read_u32(bytes, fields + 0x0C)?.max(1);
+
+        let (data, name) = read_data_and_name(bytes, base, name_offset_field(version))?;
+        check_levels(encoding, width, height, levels, fields + 0x0C, data)?;
+        let data = data.to_vec();
+
+        Ok(Tex0 {
+            name,
+            width,
+            height,
+            encoding,
+            data,
+            levels,
+            version,
+        })
+    }
+
+    /// Decodes the base level to RGBA8, four bytes per pixel, using the palette
+    /// in `plt0` for palette encodings.
+    pub fn to_rgba(&self, plt0: Option<&Plt0>) -> Result<Vec<u8>, GctconvError> {
+        let palette = plt0.map(|p| (p.format, p.data.as_slice()));
+        decode(self.encoding, self.width, self.height, &self.data, palette)
+    }
+
+    /// Everything about the texture that its encoding or the hardware
+    /// won't accept, with `plt0` as its palette.
+    pub fn validate(&self, plt0: Option<&Plt0>) -> Vec<Problem> {
+        let mut problems = check_image(
+            self.encoding,
+            self.width,
+            self.height,
+            self.levels,
+            self.data.len(),
+        );
+        // a PLT0 can hold fewer colours than the indices reach
+        if self.encoding.has_palette() && plt0.is_none() {
+            problems.push(Problem::MissingPalette(self.encoding));
+        }
+        problems
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GctconvError> {
+        let mut tex0_file = self.header_and_data()?;
+        tex0_file.extend(name_block(&self.name)?);
+        Ok(tex0_file)
+    }
+
+    /// The TEX0 without the name that trails it, which is in the string
+    /// table instead inside a BRRES.
+    pub(crate) fn header_and_data(&self) -> Result<Vec<u8>, GctconvError> {
+        if !Tex0::VERSIONS.contains(&self.version) {
+            return Err(GctconvError::UnsupportedVersion {
+                kind: FileKind::Tex0,
+                version: self.version,
+            });
+        }
+        let header_size = header_size(self.version);
+
+        // header starts with "TEX0"
+        let mut header = b"TEX0".to_vec();
+        // header == ["T", "E", "X", "0"]
+
+        // then the size of the header and image data, big endian
+        let size = (header_size + self.data.len()) as u32;
+        header.extend(&size.to_be_bytes());
+        // header == [...[0x04], FS_1, FS_2, FS_3, FS_4]
+
+        // then tex0 version as int
+        header.extend(&self.version.to_be_bytes());
+        // header == [...[0x08], V_1, V_2, V_3, V_4]
+
+        // then int 0
+        let four_byte_0 = 0_u32.to_be_bytes();
+        header.extend(&four_byte_0);
+        // header == [...[0x0C], 0_byte, 0_byte, 0_byte, 0_byte]
+
+        // then the offset of the image data, right after the header
+        header.extend(&(header_size as u32).to_be_bytes());
+        // header == [...[0x10], HS_1, HS_2, HS_3, HS_4]
+
+        if self.version == 2 {
+            // version 2 has a user data offset too, 0 for none
+            header.extend(&four_byte_0);
+            // header == [...[0x14], 0_byte, 0_byte, 0_byte, 0_byte]
+        }
+
+        // then 0x4 + size, as int: the name offset
+        header.extend(&(size + 0x4).to_be_bytes());
+        // header == [...[0x14], FS_P4_1, FS_P4_2, FS_P4_3, FS_P4_4]
+        // every field from here on is 4 bytes later in version 2
+
+        let four_byte_1 = 1_u32.to_be_bytes();
+        if self.encoding.has_palette() {
+            // int 1 if CI4, CI8 or CI14x2
+            header.extend(&four_byte_1);
+        } else {
+            // otherwise int 0
+            header.extend(&four_byte_0);
+        }
+        // header == [...[0x18], 0/1_byte, 0/1_byte, 0/1_byte, 0/1_byte]
+
+        // width as short, height as short, enc as byte
+        header.extend(&self.width.to_be_bytes());
+        // header == [...[0x1C], width_1, width_2]
+        header.extend(&self.height.to_be_bytes());
+        // header == [...[0x1E], height_1, height_2]
+
+        // padding for enc byte
+        header.extend(&[0; 3]);
+        // header == [...[0x20], 0_byte, 0_byte, 0_byte]
+
+        // encoding byte
+        header.push(self.encoding as u8);
+        // header == [...[0x23], enc_byte]
+
+        // number of images, the texture and each of its mip levels, as int
+        header.extend(&self.levels.max(1).to_be_bytes());
+        // header == [...[0x24], L_1, L_2, L_3, L_4]
+
+        // min and max LOD as floats: from the base level to the last one
+        header.extend(&0_f32.to_be_bytes());
+        header.extend(&((self.levels.max(1) - 1) as f32).to_be_bytes());
+        // header == [...[0x28], 0_byte, 0_byte, 0_byte, 0_byte, MAX_1, MAX_2, MAX_3, MAX_4]
+
+        header.resize(header_size, 0);
+        // header is now padded to 0x40, or 0x60 in version 2
+
+        let mut tex0_file = header;
+        tex0_file.extend(&self.data);
+        Ok(tex0_file)
+    }
+}
+
+impl From<&Gct> for Tex0 {
+    /// Takes the image data of a GCT, leaving the name empty; the palette of
+    /// palette encodings goes into a separate `Plt0`.
+    fn from(gct: &Gct) -> Tex0 {
+        Tex0 {
+            name: String::new(),
+            width: gct.width,
+            height: gct.height,
+            encoding: gct.encoding,
+            data: gct.image_data.clone(),
+            levels: gct.levels,
+            version: 1,
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Tex0 {
+    type Error = GctconvError;
+
+    fn try_from(bytes: &[u8]) -> Result<Tex0, GctconvError> {
+        Tex0::parse(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        read_u32(bytes, offset).unwrap()
+    }
+
+    #[test]
+    fn every_version_round_trips() {
+        // header size and where the name offset is
+        for (version, header, name_field) in [(1, 0x40, 0x14), (2, 0x60, 0x18), (3, 0x40, 0x14)] {
+            let tex0 = Tex0 {
+                name: "versioned".to_owned(),
+                width: 16,
+                height: 8,
+                encoding: EncodingType::I8,
+                // 16x8 and then 8x4, in 8x4 blocks
+                data: (0..0xA0).map(|i| i as u8).collect(),
+                levels: 2,
+                version,
+            };
+            let bytes = tex0.to_bytes().unwrap();
+            let size = header + tex0.data.len() as u32;
+
+            assert_eq!(u32_at(&bytes, 0x04), size);
+            assert_eq!(u32_at(&bytes, 0x08), version);
+            assert_eq!(u32_at(&bytes, 0x10), header);
+            assert_eq!(u32_at(&bytes, name_field), size + 4);
+            if version == 2 {
+                // no user data
+                assert_eq!(u32_at(&bytes, 0x14), 0);
+            }
+            let fields = name_field + 4;
+            assert_eq!(u32_at(&bytes, fields + 0x04), 0x0010_0008);
+            assert_eq!(u32_at(&bytes, fields + 0x0C), 2);
+            assert_eq!(&bytes[header as usize..size as usize], &tex0.data[..]);
+
+            assert_eq!(Tex0::parse(&bytes).unwrap(), tex0);
+        }
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut tex0 = Tex0 {
+            name: String::new(),
+            width: 8,
+            height: 4,
+            encoding: EncodingType::I8,
+            data: vec![0; 0x20],
+            levels: 1,
+            version: 1,
+        };
+        let mut bytes = tex0.to_bytes().unwrap();
+        bytes[0x0B] = 4;
+        assert!(matches!(
+            Tex0::parse(&bytes),
+            Err(GctconvError::UnsupportedVersion { version: 4, .. })
+        ));
+        tex0.version = 0;
+        assert!(tex0.to_bytes().is_err());
+    }
+
+    #[test]
+    fn name_is_found_through_its_offset() {
+        let tex0 = Tex0 {
+            name: "stored_name".to_owned(),
+            width: 8,
+            height: 4,
+            encoding: EncodingType::I8,
+            data: vec![0x11; 0x20],
+            levels: 1,
+            version: 1,
+        };
+        // something else between the image data and the name, such as
+        // another tool's padding
+        let mut bytes = tex0.header_and_data().unwrap();
+        let gap = bytes.len() as u32 + 0x24;
+        bytes.extend([0xEE; 0x24]);
+        bytes.extend(name_block(&tex0.name).unwrap());
+        bytes[0x14..0x18].copy_from_slice(&(gap + 4).to_be_bytes());
+
+        assert_eq!(Tex0::parse(&bytes).unwrap(), tex0);
+    }
+}
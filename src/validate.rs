@@ -0,0 +1,272 @@
+//! Checks of a texture against what its encoding and the hardware allow,
+//! before it is written out or shipped.
+
+use std::fmt;
+
+use crate::{max_levels, EncodingType};
+
+/// The widest and tallest texture the GameCube/Wii hardware can sample.
+pub const MAX_DIMENSION: u16 = 1024;
+
+/// Something about a texture that would make it load wrong, or not at all.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// A width or height of 0.
+    EmptyImage { width: u16, height: u16 },
+    /// Wider or taller than `MAX_DIMENSION`.
+    TooLarge { width: u16, height: u16 },
+    /// More mip levels than halving the texture down to 1x1 gives.
+    TooManyLevels { levels: u32, max: u32 },
+    /// Mip levels on a texture that isn't a power of two wide and high,
+    /// which the hardware can only sample for textures without them.
+    NotPowerOfTwo {
+        width: u16,
+        height: u16,
+        levels: u32,
+    },
+    /// The image data isn't the size the encoding, padded dimensions and
+    /// mip levels call for.
+    DataSize {
+        encoding: EncodingType,
+        expected: usize,
+        found: usize,
+    },
+    /// A GCT palette isn't the size its colour index encoding calls for.
+    PaletteSize {
+        encoding: EncodingType,
+        expected: usize,
+        found: usize,
+    },
+    /// A colour index texture has no palette to go with it.
+    MissingPalette(EncodingType),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::EmptyImage { width, height } => {
+                write!(f, "{}x{} texture has no pixels", width, height)
+            }
+            Problem::TooLarge { width, height } => write!(
+                f,
+                "{}x{} texture is larger than the hardware's {}x{}",
+                width, height, MAX_DIMENSION, MAX_DIMENSION
+            ),
+            Problem::TooManyLevels { levels, max } => {
+                write!(f, "texture has {} mip levels, but only {} fit", levels, max)
+            }
+            Problem::NotPowerOfTwo {
+                width,
+                height,
+                levels,
+            } => write!(
+                f,
+                "{}x{} texture has {} mip levels, but mipmapped textures need power of two sides",
+                width, height, levels
+            ),
+            Problem::DataSize {
+                encoding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "image data is {:#x} bytes, but {} needs {:#x}",
+                found,
+                encoding.name(),
+                expected
+            ),
+            Problem::PaletteSize {
+                encoding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "palette is {:#x} bytes, but {} needs {:#x}",
+                found,
+                encoding.name(),
+                expected
+            ),
+            Problem::MissingPalette(encoding) => {
+                write!(f, "{} texture has no palette", encoding.name())
+            }
+        }
+    }
+}
+
+/// The problems with the dimensions, mip levels and image data size of a
+/// texture, which GCTs and TEX0s have in common.
+pub(crate) fn check_image(
+    encoding: EncodingType,
+    width: u16,
+    height: u16,
+    levels: u32,
+    data_size: usize,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if width == 0 || height == 0 {
+        problems.push(Problem::EmptyImage { width, height });
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        problems.push(Problem::TooLarge { width, height });
+    }
+
+    let max = max_levels(width, height);
+    if levels > max {
+        problems.push(Problem::TooManyLevels { levels, max });
+    }
+    if levels > 1 && !(width.is_power_of_two() && height.is_power_of_two()) {
+        problems.push(Problem::NotPowerOfTwo {
+            width,
+            height,
+            levels,
+        });
+    }
+
+    // every level is stored in whole tiles of the encoding
+    let expected = encoding.chain_size(width, height, levels);
+    if data_size != expected {
+        problems.push(Problem::DataSize {
+            encoding,
+            expected,
+            found: data_size,
+        });
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gct, PaletteFormat, Plt0, Tex0};
+
+    fn tex0(width: u16, height: u16, encoding: EncodingType, levels: u32) -> Tex0 {
+        Tex0 {
+            name: "tex".to_owned(),
+            width,
+            height,
+            encoding,
+            data: vec![0; encoding.chain_size(width, height, levels)],
+            levels,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn valid_textures_have_no_problems() {
+        assert_eq!(tex0(16, 8, EncodingType::Cmpr, 1).validate(None), []);
+        assert_eq!(tex0(1024, 64, EncodingType::I8, 11).validate(None), []);
+        // sides that aren't a power of two are fine without mipmaps
+        assert_eq!(tex0(37, 21, EncodingType::Rgb5A3, 1).validate(None), []);
+
+        let ci8 = tex0(8, 8, EncodingType::Ci8, 4);
+        let plt0 = Plt0 {
+            name: "tex".to_owned(),
+            format: PaletteFormat::Rgb565,
+            data: vec![0; 0x200],
+        };
+        assert_eq!(ci8.validate(Some(&plt0)), []);
+        let gct = Gct::from((&ci8, &plt0));
+        assert_eq!(gct.validate(), []);
+    }
+
+    #[test]
+    fn data_size_of_every_level() {
+        // 4x4 blocks of 2 byte texels, each level padded to whole blocks
+        let sizes = [0x100, 0x40, 0x20, 0x20, 0x20];
+        for levels in 1..=5 {
+            let expected: usize = sizes[..levels].iter().sum();
+            let levels = levels as u32;
+            assert_eq!(
+                check_image(EncodingType::Rgb565, 16, 8, levels, expected),
+                []
+            );
+            for found in [expected - 2, expected + 0x20] {
+                assert_eq!(
+                    check_image(EncodingType::Rgb565, 16, 8, levels, found),
+                    [Problem::DataSize {
+                        encoding: EncodingType::Rgb565,
+                        expected,
+                        found,
+                    }]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn palette_encodings_without_a_palette() {
+        let ci4 = tex0(8, 8, EncodingType::Ci4, 1);
+        assert_eq!(
+            ci4.validate(None),
+            [Problem::MissingPalette(EncodingType::Ci4)]
+        );
+
+        let mut gct = Gct::from(&ci4);
+        gct.palette_data.clear();
+        assert_eq!(
+            gct.validate(),
+            [Problem::PaletteSize {
+                encoding: EncodingType::Ci4,
+                expected: 0x20,
+                found: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn sides_over_the_largest_size() {
+        let tex0 = tex0(2048, 8, EncodingType::I8, 1);
+        assert_eq!(
+            tex0.validate(None),
+            [Problem::TooLarge {
+                width: 2048,
+                height: 8,
+            }]
+        );
+        assert_eq!(
+            check_image(
+                EncodingType::I8,
+                8,
+                1025,
+                1,
+                EncodingType::I8.image_size(8, 1025)
+            ),
+            [Problem::TooLarge {
+                width: 8,
+                height: 1025,
+            }]
+        );
+    }
+
+    #[test]
+    fn mipmaps_need_power_of_two_sides() {
+        let tex0 = tex0(12, 8, EncodingType::Rgba32, 2);
+        assert_eq!(
+            tex0.validate(None),
+            [Problem::NotPowerOfTwo {
+                width: 12,
+                height: 8,
+                levels: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn levels_past_one_by_one() {
+        assert_eq!(
+            check_image(EncodingType::I4, 4, 4, 4, 0x60),
+            [Problem::TooManyLevels { levels: 4, max: 3 }]
+        );
+    }
+
+    #[test]
+    fn empty_images() {
+        assert_eq!(
+            check_image(EncodingType::I4, 0, 4, 1, 0x20),
+            [Problem::EmptyImage {
+                width: 0,
+                height: 4,
+            }]
+        );
+    }
+}